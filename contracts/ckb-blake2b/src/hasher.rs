@@ -0,0 +1,37 @@
+use ckbfs_hasher::{load_head_witness_state, Hasher, HasherError};
+
+pub struct Blake2b(ckbfs_blake2b::Blake2b);
+
+impl Hasher for Blake2b {
+    const NAME: &'static str = "CKB-Blake2b";
+
+    fn new() -> Self {
+        Self(ckbfs_blake2b::Blake2b::new())
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    fn checksum(&self) -> u32 {
+        self.0.checksum()
+    }
+
+    // the checksum alone is too short to bind a resumed state, the contract keeps the full digest
+    fn digest(&self) -> Option<[u8; 32]> {
+        Some(self.0.finalize())
+    }
+
+    // a digest can not be recovered from, an append resumes from the hasher state of the previous
+    // content, carried in the head witness
+    fn resume(head_index: usize, previous_checksum: u32) -> Result<Self, HasherError> {
+        let state = load_head_witness_state(head_index)?;
+        let hasher = ckbfs_blake2b::Blake2b::deserialize(&state).map_err(|_| HasherError::InvalidState)?;
+
+        // the state must be the one of the content the previous checksum was taken from
+        if hasher.checksum() != previous_checksum {
+            return Err(HasherError::InvalidState);
+        }
+        Ok(Self(hasher))
+    }
+}
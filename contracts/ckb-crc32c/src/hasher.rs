@@ -0,0 +1,30 @@
+use ckbfs_hasher::Hasher;
+use crc::{Crc, Digest, CRC_32_ISCSI};
+
+// CRC-32C, also known as CRC-32/ISCSI
+static CASTAGNOLI: Crc<u32> = Crc::<u32>::new(&CRC_32_ISCSI);
+
+// a running CRC-32C, the counterpart of adler::Adler32 in ckb-adler32
+pub struct Crc32c(Digest<'static, u32>);
+
+impl Hasher for Crc32c {
+    const NAME: &'static str = "CKB-CRC32C";
+
+    fn new() -> Self {
+        Self(CASTAGNOLI.digest())
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    fn checksum(&self) -> u32 {
+        self.0.clone().finalize()
+    }
+
+    // the register is the checksum with the final xor undone, and CRC_32_ISCSI reflects it on
+    // input, so recovering from 0 is the same as starting over
+    fn recover(checksum: u32) -> Option<Self> {
+        Some(Self(CASTAGNOLI.digest_with_initial((checksum ^ CRC_32_ISCSI.xorout).reverse_bits())))
+    }
+}
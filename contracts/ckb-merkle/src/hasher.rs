@@ -0,0 +1,37 @@
+use ckbfs_hasher::{load_head_witness_state, Hasher, HasherError};
+
+pub struct ChunkTree(ckbfs_merkle::ChunkTree);
+
+impl Hasher for ChunkTree {
+    const NAME: &'static str = "CKB-Merkle";
+
+    fn new() -> Self {
+        Self(ckbfs_merkle::ChunkTree::new())
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    fn checksum(&self) -> u32 {
+        self.0.checksum()
+    }
+
+    // the root is kept in cell data, so readers verify proofs against it and appends resume from it
+    fn digest(&self) -> Option<[u8; 32]> {
+        Some(self.0.root())
+    }
+
+    // a root can not be recovered from, an append pushes new content onto the chunk tree state of
+    // the previous content, carried in the head witness
+    fn resume(head_index: usize, previous_checksum: u32) -> Result<Self, HasherError> {
+        let state = load_head_witness_state(head_index)?;
+        let tree = ckbfs_merkle::ChunkTree::deserialize(&state).map_err(|_| HasherError::InvalidState)?;
+
+        // the state must lead to the previous checksum
+        if tree.checksum() != previous_checksum {
+            return Err(HasherError::InvalidState);
+        }
+        Ok(Self(tree))
+    }
+}
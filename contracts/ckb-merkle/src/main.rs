@@ -0,0 +1,23 @@
+#![no_std]
+#![cfg_attr(not(test), no_main)]
+
+#[cfg(test)]
+extern crate alloc;
+
+#[cfg(not(test))]
+use ckb_std::default_alloc;
+
+#[cfg(not(test))]
+ckb_std::entry!(program_entry);
+
+#[cfg(not(test))]
+const HEAP_SIZE: usize = 800 * 1024;
+
+#[cfg(not(test))]
+default_alloc!(1024, HEAP_SIZE, 64);
+
+mod hasher;
+
+pub fn program_entry() -> i8 {
+    ckbfs_hasher::run::<hasher::ChunkTree>()
+}
@@ -0,0 +1,120 @@
+use ckb_std::ckb_constants::Source;
+use ckbfs_manifest::MANIFEST_CONTENT_TYPE;
+use ckbfs_types::CKBFSData;
+use ckbfs_witness::{load_witness_exact, locate_head_witness_payload};
+
+use crate::entry::{
+    find_source, load_ckbfs_raw_data, load_type_args, unpack_type_args, validate_combine_by_spawn,
+    validate_hasher_digest, HasherScript, CKBFS_HEADER, CKBFS_VERSION_V3,
+};
+use crate::error::CKBFSError;
+use crate::fields::{load_content_digest, ContentDigest};
+
+// version byte of a concatenation head witness, v3 with the high bit set
+pub const CKBFS_VERSION_V3_CONCAT: u8 = CKBFS_VERSION_V3 | 0x80;
+
+// <"CKBFS"> <0x83> <POSITION_A> <CHECKSUM_A> <POSITION_B> <CHECKSUM_B>
+const CKBFS_V3_CONCAT_WITNESS_SIZE: usize = 86; // 5 + 1 + (36 + 4) * 2
+
+// a previous position and checksum of a source file
+pub struct ConcatSource {
+    pub tx_hash: [u8; 32],
+    pub index: u32,
+    pub checksum: u32,
+}
+
+pub struct ConcatWitness {
+    pub a: ConcatSource,
+    pub b: ConcatSource,
+}
+
+fn parse_source(source: &[u8]) -> ConcatSource {
+    ConcatSource {
+        tx_hash: source[0..32].try_into().unwrap(),
+        index: u32::from_le_bytes(source[32..36].try_into().unwrap()),
+        checksum: u32::from_le_bytes(source[36..40].try_into().unwrap()),
+    }
+}
+
+// Rule 37: returns None if the head witness is not a concatenation, it is then left to the v3 rules
+pub fn load_concat_witness(witness_index: usize) -> Result<Option<ConcatWitness>, CKBFSError> {
+    let (offset, len) = locate_head_witness_payload(witness_index, Source::Output)?;
+    let mut header = [0u8; 6];
+    if len < header.len() {
+        return Ok(None);
+    }
+    load_witness_exact(&mut header, offset, witness_index, Source::Output)?;
+    if header[0..5] != CKBFS_HEADER || header[5] != CKBFS_VERSION_V3_CONCAT {
+        return Ok(None);
+    }
+
+    // a concatenation carries no content, and chains no other witness
+    if len != CKBFS_V3_CONCAT_WITNESS_SIZE {
+        return Err(CKBFSError::InvalidWitnessHeader);
+    }
+    let mut concat_witness = [0u8; CKBFS_V3_CONCAT_WITNESS_SIZE];
+    load_witness_exact(&mut concat_witness, offset, witness_index, Source::Output)?;
+    Ok(Some(ConcatWitness {
+        a: parse_source(&concat_witness[6..46]),
+        b: parse_source(&concat_witness[46..86]),
+    }))
+}
+
+// loads a source file in CellDeps, it must share the hasher of the new file
+fn load_source(
+    source: &ConcatSource,
+    checksum_hasher: Option<HasherScript>,
+) -> Result<CKBFSData, CKBFSError> {
+    let source_index = find_source(&source.tx_hash, source.index, source.checksum)?
+        .ok_or(CKBFSError::InvalidConcatSource)?;
+    let source_type_args = load_type_args(source_index, Source::CellDep);
+    let (_, source_checksum_hasher) = unpack_type_args(&source_type_args)?;
+    if source_checksum_hasher != checksum_hasher {
+        return Err(CKBFSError::InvalidConcatSource);
+    }
+    load_ckbfs_raw_data(source_index, Source::CellDep)
+}
+
+// Rule 38: the content of a concatenation is the content of A followed by the content of B,
+// its checksum is combined from theirs without rehashing any content
+pub fn process_concatenation(
+    data: &CKBFSData,
+    concat_witness: &ConcatWitness,
+    checksum: u32,
+    checksum_hasher: Option<HasherScript>,
+    content_digest: ContentDigest,
+) -> Result<(), CKBFSError> {
+    let data_a = load_source(&concat_witness.a, checksum_hasher)?;
+    let data_b = load_source(&concat_witness.b, checksum_hasher)?;
+    let digest_a = load_content_digest(&data_a)?;
+    let digest_b = load_content_digest(&data_b)?;
+
+    // combining needs the length of B
+    let length_b = digest_b.length.ok_or(CKBFSError::InvalidConcatSource)?;
+    let hasher_digest = validate_combine_by_spawn(
+        concat_witness.a.checksum,
+        concat_witness.b.checksum,
+        length_b,
+        checksum,
+        checksum_hasher,
+    )?;
+    // a combined checksum resumes from no state
+    validate_hasher_digest(hasher_digest, data, None)?;
+
+    // content hash chains over content, and can not be combined
+    if content_digest.hash.is_some() {
+        return Err(CKBFSError::ContentHashMismatch);
+    }
+    if let Some(length) = content_digest.length {
+        if digest_a.length.and_then(|length_a| length_a.checked_add(length_b)) != Some(length) {
+            return Err(CKBFSError::LengthMismatch);
+        }
+    }
+
+    // whole entries followed by whole entries are still whole entries
+    let is_manifest = |data: &CKBFSData| data.content_type().raw_data()[..] == *MANIFEST_CONTENT_TYPE;
+    if is_manifest(data) && !(is_manifest(&data_a) && is_manifest(&data_b)) {
+        return Err(CKBFSError::InvalidManifest);
+    }
+    Ok(())
+}
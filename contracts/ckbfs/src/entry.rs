@@ -1,20 +1,46 @@
 use alloc::{ffi::CString, format, vec, vec::Vec};
+use core::cmp::Ordering;
+use core::ffi::CStr;
 use blake2b_ref::Blake2bBuilder;
-use ckb_std::high_level::{encode_hex, load_input_out_point, load_witness};
+use ckb_std::high_level::{encode_hex, load_input_out_point};
 use ckb_std::{
     ckb_constants::Source,
-    ckb_types::core::ScriptHashType,
+    ckb_types::{
+        bytes::Bytes,
+        core::ScriptHashType,
+        packed::CellDepVec,
+        prelude::Entity as _,
+    },
+    error::SysError,
     high_level::{
-        load_cell_data, load_cell_data_hash, load_cell_type, load_cell_type_hash, load_input,
-        load_script, load_script_hash, QueryIter,
+        load_cell_capacity, load_cell_data, load_cell_lock_hash, load_cell_type, load_cell_type_hash,
+        load_input, load_input_since, load_script, load_script_hash, look_for_dep_with_hash2,
+        spawn_cell, QueryIter,
     },
+    since::Since,
+    syscalls,
 };
 use core::fmt::Write;
 
 use molecule::prelude::Entity;
 
-use crate::{error::CKBFSError, hash};
+use crate::fields::{
+    extra_field, is_sealed, load_appenders, load_content_digest, load_flags, load_hasher_digest, load_metadata,
+    load_owner, ContentDigest, FLAGS_FIELD_INDEX, METADATA_APPEND_ONLY, METADATA_FIELD_INDEX, METADATA_IMMUTABLE,
+    METADATA_MUTABLE, OWNER_FIELD_INDEX,
+};
+use crate::file_info::{validate_content_type, validate_filename};
+use crate::manifest::validate_manifest_content;
+#[cfg(feature = "inline-adler32")]
+use crate::inline_adler32;
+use crate::{concat, error::CKBFSError, hash, v2};
 use ckbfs_types::CKBFSData;
+use ckbfs_types_v2::CKBFSData as CKBFSDataV2;
+use ckbfs_witness::{
+    chain_witness_indexes, load_witness_exact, locate_head_witness_payload, stream_chain_content,
+};
+
+pub use ckbfs_witness::{CKBFS_HEADER, CKBFS_V3_HEAD_WITNESS_OFFSET, CKBFS_VERSION_V3};
 
 pub fn encode_hex_0x(data: &[u8]) -> CString {
     let mut s = alloc::string::String::with_capacity(data.len() * 2 + 2);
@@ -30,7 +56,7 @@ pub fn u8_to_cstring(number: u8) -> CString {
     CString::new(s).unwrap()
 }
 
-fn load_ckbfs_raw_data(index: usize, source: Source) -> Result<CKBFSData, CKBFSError> {
+pub fn load_ckbfs_raw_data(index: usize, source: Source) -> Result<CKBFSData, CKBFSError> {
     CKBFSData::from_compatible_slice(
         load_cell_data(index, source)
             .map_err(|_| CKBFSError::ItemMissing)? // can not load data
@@ -39,7 +65,24 @@ fn load_ckbfs_raw_data(index: usize, source: Source) -> Result<CKBFSData, CKBFSE
     .map_err(|_| CKBFSError::Encoding)
 }
 
-pub fn load_type_args(index: usize, source: Source) -> molecule::bytes::Bytes {
+pub enum CKBFSCellData {
+    V2(CKBFSDataV2),
+    V3(CKBFSData),
+}
+
+// v2 layout is checked strictly before v3: a v2 cell with empty indexes is also a valid
+// v3 cell in compatible mode, while v3 data never has a valid backlinks field
+pub fn load_ckbfs_data(index: usize, source: Source) -> Result<CKBFSCellData, CKBFSError> {
+    let raw_data = load_cell_data(index, source).map_err(|_| CKBFSError::ItemMissing)?;
+    if let Ok(data) = CKBFSDataV2::from_slice(&raw_data) {
+        return Ok(CKBFSCellData::V2(data));
+    }
+    CKBFSData::from_compatible_slice(&raw_data)
+        .map(CKBFSCellData::V3)
+        .map_err(|_| CKBFSError::Encoding)
+}
+
+pub fn load_type_args(index: usize, source: Source) -> Bytes {
     load_cell_type(index, source)
         .unwrap_or(None)
         .unwrap_or_default()
@@ -67,96 +110,406 @@ pub fn validate_type_id(type_id: &[u8; 32], output_index: usize) -> bool {
     }
 }
 
-pub fn validate_by_spawn_v3(
-    witness_index: u32,
-    checksum: u32,
-    code_hash: Option<&[u8; 32]>,
-) -> Result<bool, CKBFSError> {
-    let code_hash = code_hash.unwrap_or(&hash::CKB_ADLER32_CODE_HASH);
-    let hasher_index = QueryIter::new(load_cell_data_hash, Source::CellDep)
-        .position(|data_hash| data_hash.as_slice() == code_hash);
-    if hasher_index.is_none() {
+/// Checksum hasher set in Type args, located in CellDeps by `code_hash` and `hash_type` like a script.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct HasherScript<'a> {
+    pub code_hash: &'a [u8; 32],
+    pub hash_type: ScriptHashType,
+}
+
+// the built-in adler32 hasher, used when Type args set no hasher
+pub const DEFAULT_HASHER: HasherScript<'static> = HasherScript {
+    code_hash: &hash::CKB_ADLER32_CODE_HASH,
+    hash_type: ScriptHashType::Data1,
+};
+
+/// Full digests reported by a hasher which has one, its checksum alone is too short to bind a state
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub struct HasherDigest {
+    // digest of the content
+    pub digest: Option<[u8; 32]>,
+    // digest of the previous content the hasher resumed from
+    pub previous: Option<[u8; 32]>,
+}
+
+/// Spawns the hasher with args, returns the checksum and the digests reported by the hasher.
+///
+/// The hasher inherits the write end of a pipe and writes the computed checksum back as 4 bytes
+/// little endian, followed by the digest of the content and the digest of the previous content
+/// if it has them. Its exit code tells whether the checksum matches the expected one.
+pub fn spawn_hasher(hasher: HasherScript, args: &[&CStr]) -> Result<(u32, HasherDigest), CKBFSError> {
+    // a hasher referenced by type is matched with type hash, otherwise with data hash
+    if look_for_dep_with_hash2(hasher.code_hash, hasher.hash_type).is_err() {
         return Err(CKBFSError::NoChecksumHasherFound);
     }
 
-    let mode = u8_to_cstring(3u8);
-    let witness_index_arg = encode_hex_0x(&witness_index.to_le_bytes());
+    let (read_fd, write_fd) = syscalls::pipe().map_err(|_| CKBFSError::HasherFailure)?;
+    let pid = spawn_cell(hasher.code_hash, hasher.hash_type, args, &[write_fd])
+        .map_err(|_| CKBFSError::HasherFailure)?;
+
+    // the write end belongs to the hasher now, it is closed once the hasher exits,
+    // so read the reply to the end before waiting, or the hasher blocks on writing it
+    let mut reply = Vec::new();
+    let mut buf = [0u8; 64];
+    loop {
+        match syscalls::read(read_fd, &mut buf) {
+            Ok(0) | Err(SysError::OtherEndClosed) => break,
+            Ok(len) => reply.extend_from_slice(&buf[..len]),
+            Err(_) => return Err(CKBFSError::HasherFailure),
+        }
+    }
+    let _ = syscalls::close(read_fd);
+    let exit_code = syscalls::wait(pid).map_err(|_| CKBFSError::HasherFailure)?;
+    if exit_code != 0 {
+        // the hasher exits with a distinct code for every failure, e.g. a malformed arg or witness
+        return Err(CKBFSError::from_hasher_exit_code(exit_code));
+    }
+
+    // <CHECKSUM, 4 bytes> [<DIGEST, 32 bytes> [<PREVIOUS_DIGEST, 32 bytes>]]
+    if ![4, 36, 68].contains(&reply.len()) {
+        return Err(CKBFSError::HasherFailure);
+    }
+    let checksum = u32::from_le_bytes(reply[0..4].try_into().unwrap());
+    let digest_at = |offset: usize| reply.get(offset..offset + 32).map(|digest| digest.try_into().unwrap());
+    Ok((
+        checksum,
+        HasherDigest {
+            digest: digest_at(4),
+            previous: digest_at(36),
+        },
+    ))
+}
+
+pub fn validate_by_spawn(
+    mode: u8,
+    witnesses_arg: &CStr,
+    checksum: u32,
+    recover: Option<u32>,
+    hasher: Option<HasherScript>,
+) -> Result<HasherDigest, CKBFSError> {
+    let hasher = hasher.unwrap_or(DEFAULT_HASHER);
+
+    let mode = u8_to_cstring(mode);
     let checksum_arg = encode_hex_0x(&checksum.to_le_bytes());
-    let exec_args = vec![
-        mode.as_c_str(),
-        witness_index_arg.as_c_str(),
-        checksum_arg.as_c_str(),
-    ];
+    let recover_arg = recover.map(|recover| encode_hex_0x(&recover.to_le_bytes()));
+    let mut spawn_args = vec![mode.as_c_str(), witnesses_arg, checksum_arg.as_c_str()];
+    if let Some(recover_arg) = recover_arg.as_ref() {
+        spawn_args.push(recover_arg.as_c_str());
+    }
 
-    match ckb_std::high_level::exec_cell(code_hash, ScriptHashType::Data1, &exec_args) {
-        Ok(_) => Ok(true),
-        Err(_) => Err(CKBFSError::ChecksumMismatch),
+    let (reported_checksum, digest) = spawn_hasher(hasher, &spawn_args)?;
+    if reported_checksum != checksum {
+        return Err(CKBFSError::ChecksumMismatch);
     }
+    Ok(digest)
 }
 
-fn validate_by_spawn_v3_with_recover(
+pub fn validate_by_spawn_v3(
     witness_index: u32,
     checksum: u32,
-    recover: u32,
-    code_hash: Option<&[u8; 32]>,
-) -> Result<bool, CKBFSError> {
-    let code_hash = code_hash.unwrap_or(&hash::CKB_ADLER32_CODE_HASH);
-    let hasher_index = QueryIter::new(load_cell_data_hash, Source::CellDep)
-        .position(|data_hash| data_hash.as_slice() == code_hash);
-    if hasher_index.is_none() {
-        return Err(CKBFSError::NoChecksumHasherFound);
+    recover: Option<u32>,
+    hasher: Option<HasherScript>,
+) -> Result<HasherDigest, CKBFSError> {
+    #[cfg(feature = "inline-adler32")]
+    if inline_adler32::is_inline_hasher(hasher) {
+        return inline_adler32::validate_v3(witness_index, checksum, recover).map(|()| HasherDigest::default());
     }
 
-    let mode = u8_to_cstring(3u8);
     let witness_index_arg = encode_hex_0x(&witness_index.to_le_bytes());
+    validate_by_spawn(3, &witness_index_arg, checksum, recover, hasher)
+}
+
+// hasher mode validating the checksum of A || B from the checksums of A and B, and the length of B,
+// args should be: [CHECKSUM_A, CHECKSUM_B, LENGTH_B, EXPECT_CHECKSUM]
+const HASHER_MODE_COMBINE: u8 = 4;
+
+pub fn validate_combine_by_spawn(
+    checksum_a: u32,
+    checksum_b: u32,
+    length_b: u64,
+    checksum: u32,
+    hasher: Option<HasherScript>,
+) -> Result<HasherDigest, CKBFSError> {
+    #[cfg(feature = "inline-adler32")]
+    if inline_adler32::is_inline_hasher(hasher) {
+        return inline_adler32::validate_combine(checksum_a, checksum_b, length_b, checksum)
+            .map(|()| HasherDigest::default());
+    }
+
+    let hasher = hasher.unwrap_or(DEFAULT_HASHER);
+    let mode = u8_to_cstring(HASHER_MODE_COMBINE);
+    let checksum_a_arg = encode_hex_0x(&checksum_a.to_le_bytes());
+    let checksum_b_arg = encode_hex_0x(&checksum_b.to_le_bytes());
+    let length_b_arg = encode_hex_0x(&length_b.to_le_bytes());
     let checksum_arg = encode_hex_0x(&checksum.to_le_bytes());
-    let recover_arg = encode_hex_0x(&recover.to_le_bytes());
-    let exec_args = vec![
+    let spawn_args = [
         mode.as_c_str(),
-        witness_index_arg.as_c_str(),
+        checksum_a_arg.as_c_str(),
+        checksum_b_arg.as_c_str(),
+        length_b_arg.as_c_str(),
         checksum_arg.as_c_str(),
-        recover_arg.as_c_str(),
     ];
 
-    match ckb_std::high_level::exec_cell(code_hash, ScriptHashType::Data1, &exec_args) {
-        Ok(_) => Ok(true),
-        Err(_) => Err(CKBFSError::ChecksumMismatch),
+    let (reported_checksum, digest) = spawn_hasher(hasher, &spawn_args)?;
+    if reported_checksum != checksum {
+        return Err(CKBFSError::ChecksumMismatch);
     }
+    Ok(digest)
 }
 
-fn unpack_type_args(args: &[u8]) -> Result<([u8; 32], Option<&[u8; 32]>), CKBFSError> {
+// Type args: <TypeID, 32 bytes>,[<hasher_code_hash, 32 bytes>, [<hasher_hash_type, 1 byte>]]
+pub fn unpack_type_args(args: &[u8]) -> Result<([u8; 32], Option<HasherScript<'_>>), CKBFSError> {
     if args.len() < 32 {
         return Err(CKBFSError::LengthNotEnough);
     }
     let type_id: [u8; 32] = args[0..32].try_into().unwrap();
-    let mut checksum_code_hash = None;
+    let mut checksum_hasher = None;
     if args.len() >= 64 {
-        checksum_code_hash = Some(args[32..64].try_into().unwrap());
+        // hash type is data1 if not specified, same as before hash type was introduced
+        let hash_type = match args.get(64) {
+            None | Some(2) => ScriptHashType::Data1,
+            Some(0) => ScriptHashType::Data,
+            Some(1) => ScriptHashType::Type,
+            Some(4) => ScriptHashType::Data2,
+            Some(_) => return Err(CKBFSError::InvalidHasherHashType),
+        };
+        let hasher = HasherScript {
+            code_hash: args[32..64].try_into().unwrap(),
+            hash_type,
+        };
+        // the default hasher named explicitly, e.g. to reach the file flags, is the same as no hasher,
+        // so such a file can still be forked from or concatenated with files naming none
+        if hasher != DEFAULT_HASHER {
+            checksum_hasher = Some(hasher);
+        }
     }
-    return Ok((type_id, checksum_code_hash));
+    return Ok((type_id, checksum_hasher));
 }
 
-fn validate_witness_previous_position(
-    witness_index: usize,
-    expected_tx_hash: &[u8],
-    expected_witness_index: u32,
-) -> Result<(), CKBFSError> {
-    let head_witness = load_witness(witness_index, Source::Output)?;
-    
+// Type args may continue with <file_flags, 1 byte>,[<lock_up_since, 8 bytes>] after the hasher hash type
+const TYPE_ARGS_FILE_FLAGS_OFFSET: usize = 65;
+// the file can be destroyed to reclaim its capacity
+pub const FILE_FLAG_EPHEMERAL: u8 = 0b0000_0001;
+
+// Rule 30: returns the since lock-up of an ephemeral file, 0 if it can be destroyed any time,
+// or None for a permanent file
+pub fn unpack_ephemeral_args(args: &[u8]) -> Result<Option<u64>, CKBFSError> {
+    let (flags, lock_up) = match args.get(TYPE_ARGS_FILE_FLAGS_OFFSET..) {
+        None | Some([]) => return Ok(None),
+        Some([flags]) => (*flags, 0),
+        Some([flags, since @ ..]) if since.len() == 8 => (*flags, u64::from_le_bytes(since.try_into().unwrap())),
+        Some(_) => return Err(CKBFSError::InvalidTypeArgs),
+    };
+
+    match flags {
+        0 if lock_up == 0 => Ok(None),
+        FILE_FLAG_EPHEMERAL if lock_up == 0 => Ok(Some(0)),
+        FILE_FLAG_EPHEMERAL if Since::new(lock_up).extract_lock_value().is_some() => Ok(Some(lock_up)),
+        _ => Err(CKBFSError::InvalidTypeArgs),
+    }
+}
+
+pub struct HeadWitness {
+    pub previous_tx_hash: [u8; 32],
+    pub previous_index: u32,
+    pub previous_checksum: u32,
+    pub next_index: u32,
+}
+
+impl HeadWitness {
+    pub fn has_previous_position(&self) -> bool {
+        self.previous_tx_hash != [0u8; 32] || self.previous_index != 0
+    }
+}
+
+fn load_head_witness(witness_index: usize) -> Result<HeadWitness, CKBFSError> {
+    // only the fixed part is loaded, content is left to the hasher and can be larger than the heap
+    let (offset, len) = locate_head_witness_payload(witness_index, Source::Output)?;
+    let mut head_witness = [0u8; CKBFS_V3_HEAD_WITNESS_OFFSET];
+    let len = core::cmp::min(len, head_witness.len());
+    load_witness_exact(&mut head_witness[..len], offset, witness_index, Source::Output)?;
+    parse_head_witness(&head_witness[..len])
+}
+
+pub fn parse_head_witness(head_witness: &[u8]) -> Result<HeadWitness, CKBFSError> {
+    // Rule 7: Head Witness must start with "CKBFS"
+    if !head_witness.starts_with(&CKBFS_HEADER) {
+        return Err(CKBFSError::InvalidWitnessHeader);
+    }
+
+    // Rule 8: the 6th byte is the protocol version
+    if head_witness.get(5) != Some(&CKBFS_VERSION_V3) {
+        return Err(CKBFSError::InvalidVersion);
+    }
+
     // Parse Head Witness according to RFC v3 specification
-    if head_witness.len() < 50 {
+    if head_witness.len() < CKBFS_V3_HEAD_WITNESS_OFFSET {
         return Err(CKBFSError::LengthNotEnough);
     }
-    
-    // Extract previous TX hash (bytes 6-37) and witness index (bytes 38-41)
-    let witness_previous_tx_hash: [u8; 32] = head_witness[6..38].try_into().unwrap();
-    let witness_previous_index = u32::from_le_bytes(head_witness[38..42].try_into().unwrap());
-    
+
+    // previous TX hash (bytes 6-37), witness index (bytes 38-41), checksum (bytes 42-45)
+    // and next index (bytes 46-49)
+    Ok(HeadWitness {
+        previous_tx_hash: head_witness[6..38].try_into().unwrap(),
+        previous_index: u32::from_le_bytes(head_witness[38..42].try_into().unwrap()),
+        previous_checksum: u32::from_le_bytes(head_witness[42..46].try_into().unwrap()),
+        next_index: u32::from_le_bytes(head_witness[46..50].try_into().unwrap()),
+    })
+}
+
+pub fn validate_head_witness(
+    head_witness: &HeadWitness,
+    expected_tx_hash: &[u8],
+    expected_witness_index: u32,
+    expected_checksum: u32,
+) -> Result<(), CKBFSError> {
     // Validate previous position matches expected values
-    if witness_previous_tx_hash != expected_tx_hash || witness_previous_index != expected_witness_index {
+    if head_witness.previous_tx_hash != expected_tx_hash
+        || head_witness.previous_index != expected_witness_index
+    {
         return Err(CKBFSError::InvalidPreviousPosition);
     }
-    
+
+    // Rule 10: previous checksum must be the checksum we are recovering from,
+    // otherwise an appender could make the hasher resume from an arbitrary state
+    if head_witness.previous_checksum != expected_checksum {
+        return Err(CKBFSError::InvalidPreviousChecksum);
+    }
+
+    Ok(())
+}
+
+// Loads cell deps directly referenced by this transaction,
+// only the raw part of the transaction is loaded, so witnesses do not count here.
+fn load_raw_cell_deps() -> Result<CellDepVec, CKBFSError> {
+    // Transaction: table { raw, witnesses }
+    let mut tx_header = [0u8; 12];
+    load_transaction_partial(&mut tx_header, 0)?;
+    let raw_offset = u32::from_le_bytes(tx_header[4..8].try_into().unwrap()) as usize;
+
+    // RawTransaction: table { version, cell_deps, header_deps, inputs, outputs, outputs_data }
+    let mut raw_header = [0u8; 16];
+    load_transaction_partial(&mut raw_header, raw_offset)?;
+    let cell_deps_start = u32::from_le_bytes(raw_header[8..12].try_into().unwrap()) as usize;
+    let cell_deps_end = u32::from_le_bytes(raw_header[12..16].try_into().unwrap()) as usize;
+    if cell_deps_end < cell_deps_start {
+        return Err(CKBFSError::Encoding);
+    }
+
+    let mut cell_deps = vec![0u8; cell_deps_end - cell_deps_start];
+    load_transaction_partial(&mut cell_deps, raw_offset + cell_deps_start)?;
+    CellDepVec::from_slice(&cell_deps).map_err(|_| CKBFSError::Encoding)
+}
+
+fn load_transaction_partial(buf: &mut [u8], offset: usize) -> Result<(), CKBFSError> {
+    match syscalls::load_transaction(buf, offset) {
+        Ok(_) | Err(SysError::LengthNotEnough(_)) => Ok(()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+// dep_type of a CellDep referencing the cell itself, rather than a dep group
+const DEP_TYPE_CODE: u8 = 0;
+
+// Finds the CKBFS cell in CellDeps stored at a previous position, as a fork or a concatenation points to.
+//
+// The cell itself must be created by the transaction at the previous position, so resolved CellDeps
+// are zipped with the cell deps of the transaction to get their out points. They only line up until
+// the first dep group, which expands to the cells it lists, so a source must be placed before it.
+pub fn find_source(tx_hash: &[u8; 32], witness_index: u32, checksum: u32) -> Result<Option<usize>, CKBFSError> {
+    let script = load_script()?;
+    for (index, cell_dep) in load_raw_cell_deps()?.into_iter().enumerate() {
+        if u8::from(cell_dep.dep_type()) != DEP_TYPE_CODE {
+            break;
+        }
+        if cell_dep.out_point().tx_hash().as_slice() != tx_hash {
+            continue;
+        }
+        let is_ckbfs = load_cell_type(index, Source::CellDep)?.is_some_and(|type_script| {
+            type_script.code_hash().as_slice() == script.code_hash().as_slice()
+                && type_script.hash_type() == script.hash_type()
+        });
+        if !is_ckbfs {
+            continue;
+        }
+        if let Ok(CKBFSCellData::V3(data)) = load_ckbfs_data(index, Source::CellDep) {
+            if data.index().as_slice() == witness_index.to_le_bytes()
+                && data.checksum().as_slice() == checksum.to_le_bytes()
+            {
+                return Ok(Some(index));
+            }
+        }
+    }
+    Ok(None)
+}
+
+// Rule 27: content hash is blake2b(content) for a new file, and blake2b(previous_hash || content)
+// when the content follows a previous state, as in append and fork
+// Rule 28: length is the previous length plus the length of all content parts
+fn validate_content_digest(
+    expected: ContentDigest,
+    witness_index: u32,
+    previous: ContentDigest,
+) -> Result<(), CKBFSError> {
+    if expected == ContentDigest::default() {
+        return Ok(());
+    }
+
+    let mut blake2b = Blake2bBuilder::new(32)
+        .personal(b"ckb-default-hash")
+        .build();
+    if let Some(previous_hash) = previous.hash {
+        blake2b.update(&previous_hash);
+    }
+    let mut length = previous.length.unwrap_or(0);
+    stream_chain_content(witness_index as usize, Source::Output, |part| {
+        if expected.hash.is_some() {
+            blake2b.update(part);
+        }
+        length += part.len() as u64;
+    })?;
+
+    if let Some(expected_hash) = expected.hash {
+        let mut content_hash = [0u8; 32];
+        blake2b.finalize(&mut content_hash);
+        if content_hash != expected_hash {
+            return Err(CKBFSError::ContentHashMismatch);
+        }
+    }
+    if expected.length.is_some_and(|expected_length| expected_length != length) {
+        return Err(CKBFSError::LengthMismatch);
+    }
+    Ok(())
+}
+
+// a file with content hash or length keeps it, a file without can not start one halfway
+fn validate_content_digest_presence(
+    digest: &ContentDigest,
+    previous: &ContentDigest,
+) -> Result<(), CKBFSError> {
+    if digest.hash.is_some() && previous.hash.is_none() {
+        return Err(CKBFSError::ContentHashMismatch);
+    }
+    if digest.length.is_some() && previous.length.is_none() {
+        return Err(CKBFSError::LengthMismatch);
+    }
+    Ok(())
+}
+
+// Rule 39: a hasher with a full digest keeps it in the hasher digest field, and can only resume from
+// the state of the previous content if it finalizes to the digest kept for that content
+pub fn validate_hasher_digest(
+    reported: HasherDigest,
+    data: &CKBFSData,
+    previous_digest: Option<[u8; 32]>,
+) -> Result<(), CKBFSError> {
+    let expected = HasherDigest {
+        digest: load_hasher_digest(data)?,
+        previous: previous_digest,
+    };
+    if reported != expected {
+        return Err(CKBFSError::HasherDigestMismatch);
+    }
     Ok(())
 }
 
@@ -164,23 +517,108 @@ fn process_creation(index: usize) -> Result<(), CKBFSError> {
     let data = load_ckbfs_raw_data(index, Source::Output)?;
 
     let type_script_args = load_type_args(index, Source::Output);
-    let (type_id, checksum_code_hash) = unpack_type_args(&type_script_args)?;
+    let (type_id, checksum_hasher) = unpack_type_args(&type_script_args)?;
+    unpack_ephemeral_args(&type_script_args)?;
 
     // validate unique id
     if !validate_type_id(&type_id, index) {
         return Err(CKBFSError::InvalidTypeId);
     }
 
+    validate_filename(&data.filename().raw_data())?;
+    validate_content_type(&data.content_type().raw_data())?;
+
     let checksum = u32::from_le_bytes(data.checksum().as_slice().try_into().unwrap());
     let witness_index = u32::from_le_bytes(data.index().as_slice().try_into().unwrap());
 
-    // For creation, previous position should be all zeros
-    validate_witness_previous_position(witness_index as usize, &[0u8; 32], 0)?;
+    // a file can be sealed, name its appenders and owner, or carry metadata right at publish
+    load_flags(&data)?;
+    load_appenders(&data)?;
+    load_owner(&data)?;
+    load_metadata(&data)?;
+    load_hasher_digest(&data)?;
 
-    if !validate_by_spawn_v3(witness_index, checksum, checksum_code_hash)? {
-        return Err(CKBFSError::ChecksumMismatch);
+    let content_digest = load_content_digest(&data)?;
+
+    // a concatenation has no content of its own, it is checked against its two sources
+    if let Some(concat_witness) = concat::load_concat_witness(witness_index as usize)? {
+        return concat::process_concatenation(&data, &concat_witness, checksum, checksum_hasher, content_digest);
     }
 
+    let head_witness = load_head_witness(witness_index as usize)?;
+    if head_witness.has_previous_position() {
+        // a creation with previous position is a fork of the CKBFS cell in CellDeps
+        process_fork(&data, &head_witness, witness_index, checksum, checksum_hasher, content_digest)?;
+    } else {
+        // Rule 12: for creation, previous position and previous checksum should be all zeros
+        validate_head_witness(&head_witness, &[0u8; 32], 0, 0)?;
+
+        let hasher_digest = validate_by_spawn_v3(witness_index, checksum, None, checksum_hasher)?;
+        validate_hasher_digest(hasher_digest, &data, None)?;
+        validate_content_digest(content_digest, witness_index, ContentDigest::default())?;
+    }
+    validate_manifest_content(&data, witness_index)
+}
+
+fn process_fork(
+    data: &CKBFSData,
+    head_witness: &HeadWitness,
+    witness_index: u32,
+    checksum: u32,
+    checksum_hasher: Option<HasherScript>,
+    content_digest: ContentDigest,
+) -> Result<(), CKBFSError> {
+    let source_index = find_source(
+        &head_witness.previous_tx_hash,
+        head_witness.previous_index,
+        head_witness.previous_checksum,
+    )?
+    .ok_or(CKBFSError::InvalidForkSource)?;
+
+    // recovering from the source checksum only makes sense with the same hasher
+    let source_type_args = load_type_args(source_index, Source::CellDep);
+    let (_, source_checksum_hasher) = unpack_type_args(&source_type_args)?;
+    if source_checksum_hasher != checksum_hasher {
+        return Err(CKBFSError::InvalidForkSource);
+    }
+
+    // Rule 18: new checksum should be hasher.recover_from(source_checksum).update(new_content_bytes)
+    let hasher_digest = validate_by_spawn_v3(
+        witness_index,
+        checksum,
+        Some(head_witness.previous_checksum),
+        checksum_hasher,
+    )?;
+    let source_data = load_ckbfs_raw_data(source_index, Source::CellDep)?;
+    validate_hasher_digest(hasher_digest, data, load_hasher_digest(&source_data)?)?;
+
+    // a forked file may leave out content hash and length, but can only chain them from the source's
+    let source_digest = load_content_digest(&source_data)?;
+    validate_content_digest_presence(&content_digest, &source_digest)?;
+    validate_content_digest(content_digest, witness_index, source_digest)
+}
+
+// Rule 29: immutable metadata entries are kept as they are, append-only entries keep their policy
+// and can only be extended, mutable entries can be changed or removed, and new entries can be added
+fn validate_metadata_update(input_data: &CKBFSData, output_data: &CKBFSData) -> Result<(), CKBFSError> {
+    let output_metadata = load_metadata(output_data)?;
+    for entry in load_metadata(input_data)? {
+        if entry.policy == METADATA_MUTABLE {
+            continue;
+        }
+        let updated = output_metadata
+            .iter()
+            .find(|updated| updated.key == entry.key)
+            .ok_or(CKBFSError::InvalidMetadataUpdate)?;
+        let valid = match entry.policy {
+            METADATA_IMMUTABLE => updated.policy == entry.policy && updated.value == entry.value,
+            METADATA_APPEND_ONLY => updated.policy == entry.policy && updated.value.starts_with(entry.value),
+            _ => false,
+        };
+        if !valid {
+            return Err(CKBFSError::InvalidMetadataUpdate);
+        }
+    }
     Ok(())
 }
 
@@ -206,43 +644,174 @@ fn process_update(input_index: usize, output_index: usize) -> Result<(), CKBFSEr
         return Err(CKBFSError::InvalidFieldUpdate);
     }
 
+    // the extended fields of the output must be well formed on every update, a transfer included
+    load_flags(&output_data)?;
+    load_appenders(&output_data)?;
+    load_owner(&output_data)?;
+    load_metadata(&output_data)?;
+    load_hasher_digest(&output_data)?;
+
+    validate_metadata_update(&input_data, &output_data)?;
+
     let previous_tx_hash = previous_output.tx_hash();
     let previous_witness_index = u32::from_le_bytes(input_data.index().as_slice().try_into().unwrap());
     let output_witness_index = u32::from_le_bytes(output_data.index().as_slice().try_into().unwrap());
 
-    // Validate witness previous position
-    validate_witness_previous_position(output_witness_index as usize, previous_tx_hash.as_slice(), previous_witness_index)?;
-
-    let type_script_args = load_script()?.args();
-    let (_, checksum_code_hash) = unpack_type_args(type_script_args.as_slice())?;
-    
     let input_checksum = u32::from_le_bytes(input_data.checksum().as_slice().try_into().unwrap());
     let output_checksum = u32::from_le_bytes(output_data.checksum().as_slice().try_into().unwrap());
 
+    // Rule 24: a sealed file can only be transferred with unchanged checksum, and it stays sealed
+    let (input_sealed, output_sealed) = (is_sealed(&input_data)?, is_sealed(&output_data)?);
+    if input_sealed && (!output_sealed || input_checksum != output_checksum) {
+        return Err(CKBFSError::FileSealed);
+    }
+
+    let input_digest = load_content_digest(&input_data)?;
+    let output_digest = load_content_digest(&output_data)?;
+    validate_content_digest_presence(&output_digest, &input_digest)?;
+    validate_content_digest_presence(&input_digest, &output_digest)?;
+
+    // Validate witness previous position and previous checksum
+    let head_witness = load_head_witness(output_witness_index as usize)?;
+    validate_head_witness(
+        &head_witness,
+        previous_tx_hash.as_slice(),
+        previous_witness_index,
+        input_checksum,
+    )?;
+
+    let (_, checksum_hasher) = unpack_type_args(&output_type_args)?;
+
+    // Rule 26.1: a file with an owner is controlled by an input locked by the owner, not by its cell lock
+    let owner = load_owner(&input_data)?;
+    let owner_unlocked = owner.is_none_or(|owner| has_input_locked_by(&[owner]));
+
     // Check if this is a transfer operation
     if input_checksum == output_checksum {
         // Transfer operation: Rule 16 - checksum cannot be updated
         // Rule 15: Head witness should not contain content part bytes (only backlink info)
-        return process_transfer(output_witness_index, output_checksum, checksum_code_hash);
+        if input_digest.hash != output_digest.hash {
+            return Err(CKBFSError::ContentHashMismatch);
+        }
+        if input_digest.length != output_digest.length {
+            return Err(CKBFSError::LengthMismatch);
+        }
+        if !owner_unlocked {
+            return Err(CKBFSError::UnauthorizedUpdate);
+        }
+        let hasher_digest = process_transfer(output_witness_index, output_checksum, input_checksum, checksum_hasher)?;
+        return validate_hasher_digest(hasher_digest, &output_data, load_hasher_digest(&input_data)?);
+    }
+
+    // Rule 26: an append keeps the appender set and the lock, and needs an input locked by an appender if any
+    let appenders = load_appenders(&input_data)?;
+    if appenders != load_appenders(&output_data)? {
+        return Err(CKBFSError::InvalidFieldUpdate);
+    }
+    if !appenders.is_empty()
+        && load_cell_lock_hash(input_index, Source::Input)? != load_cell_lock_hash(output_index, Source::Output)?
+    {
+        return Err(CKBFSError::InvalidAppend);
+    }
+    match owner {
+        // appenders restrict which holders of the cell lock can append
+        None => {
+            if !appenders.is_empty() && !has_input_locked_by(&appenders) {
+                return Err(CKBFSError::UnauthorizedAppend);
+            }
+        }
+        Some(_) if owner_unlocked => {}
+        // Rule 26.1: an appender can append without the owner, but only adds content
+        Some(_) => {
+            if !has_input_locked_by(&appenders) {
+                return Err(CKBFSError::UnauthorizedAppend);
+            }
+            let unchanged = |index| extra_field(&input_data, index) == extra_field(&output_data, index);
+            if ![FLAGS_FIELD_INDEX, OWNER_FIELD_INDEX, METADATA_FIELD_INDEX].into_iter().all(unchanged) {
+                return Err(CKBFSError::InvalidFieldUpdate);
+            }
+            // the cell lock may be open, the appender can not take capacity out of the file cell
+            if load_cell_capacity(input_index, Source::Input)? != load_cell_capacity(output_index, Source::Output)? {
+                return Err(CKBFSError::InvalidAppend);
+            }
+        }
     }
 
     // Append operation: Rule 13 - new checksum should be hasher.recover_from(previous_checksum).update(new_content_bytes)
-    process_append(output_witness_index, output_checksum, input_checksum, checksum_code_hash)
+    let hasher_digest = process_append(output_witness_index, output_checksum, input_checksum, checksum_hasher)?;
+    validate_hasher_digest(hasher_digest, &output_data, load_hasher_digest(&input_data)?)?;
+    validate_content_digest(output_digest, output_witness_index, input_digest)?;
+    validate_manifest_content(&output_data, output_witness_index)
 }
 
-fn process_transfer(witness_index: u32, checksum: u32, checksum_code_hash: Option<&[u8; 32]>) -> Result<(), CKBFSError> {
+fn process_transfer(witness_index: u32, checksum: u32, recover_checksum: u32, checksum_hasher: Option<HasherScript>) -> Result<HasherDigest, CKBFSError> {
     // For transfer, we validate that the witness structure is correct but no content is added
     // The hasher will validate the witness structure according to RFC v3 transfer rules
-    if !validate_by_spawn_v3(witness_index, checksum, checksum_code_hash)? {
-        return Err(CKBFSError::ChecksumMismatch);
+    validate_by_spawn_v3(witness_index, checksum, Some(recover_checksum), checksum_hasher)
+}
+
+fn process_append(witness_index: u32, checksum: u32, recover_checksum: u32, checksum_hasher: Option<HasherScript>) -> Result<HasherDigest, CKBFSError> {
+    // For append, we validate with recovery from previous checksum
+    validate_by_spawn_v3(witness_index, checksum, Some(recover_checksum), checksum_hasher)
+}
+
+fn has_input_locked_by(lock_hashes: &[[u8; 32]]) -> bool {
+    QueryIter::new(load_cell_lock_hash, Source::Input).any(|lock_hash| lock_hashes.contains(&lock_hash))
+}
+
+// witnesses holding the content of a CKBFS cell in outputs, including the head witness of v3
+fn claimed_witnesses(index: usize) -> Result<Vec<usize>, CKBFSError> {
+    match load_ckbfs_data(index, Source::Output)? {
+        CKBFSCellData::V2(data) => Ok(v2::claimed_witnesses(&data)),
+        CKBFSCellData::V3(data) => {
+            let witness_index = u32::from_le_bytes(data.index().as_slice().try_into().unwrap());
+            if concat::load_concat_witness(witness_index as usize)?.is_some() {
+                return Ok(vec![witness_index as usize]);
+            }
+            Ok(chain_witness_indexes(witness_index as usize, Source::Output)?)
+        }
+    }
+}
+
+// Rule 35: CKBFS cells of the same code hash in outputs can not share witnesses, every script group
+// checks its own cell against all the others
+fn validate_disjoint_witnesses(output_index: usize) -> Result<(), CKBFSError> {
+    let script = load_script()?;
+    let claimed = claimed_witnesses(output_index)?;
+    for (index, type_script) in QueryIter::new(load_cell_type, Source::Output).enumerate() {
+        let is_other_ckbfs = index != output_index
+            && type_script.is_some_and(|type_script| {
+                type_script.code_hash().as_slice() == script.code_hash().as_slice()
+                    && type_script.hash_type() == script.hash_type()
+            });
+        if is_other_ckbfs && claimed_witnesses(index)?.iter().any(|index| claimed.contains(index)) {
+            return Err(CKBFSError::SharedWitness);
+        }
     }
     Ok(())
 }
 
-fn process_append(witness_index: u32, checksum: u32, recover_checksum: u32, checksum_code_hash: Option<&[u8; 32]>) -> Result<(), CKBFSError> {
-    // For append, we validate with recovery from previous checksum
-    if !validate_by_spawn_v3_with_recover(witness_index, checksum, recover_checksum, checksum_code_hash)? {
-        return Err(CKBFSError::ChecksumMismatch);
+// Rule 4, Rule 30: a CKBFS cell can only be destroyed if it is ephemeral, and its lock-up has passed
+fn process_destruction(index: usize) -> Result<(), CKBFSError> {
+    let type_script_args = load_type_args(index, Source::Input);
+    let lock_up = match unpack_ephemeral_args(&type_script_args)? {
+        None => return Err(CKBFSError::DeletionForbidden),
+        Some(lock_up) => lock_up,
+    };
+
+    // Rule 26.1: only the owner can destroy a file with an owner
+    let owner = load_owner(&load_ckbfs_raw_data(index, Source::Input)?)?;
+    if owner.is_some_and(|owner| !has_input_locked_by(&[owner])) {
+        return Err(CKBFSError::UnauthorizedUpdate);
+    }
+
+    // the chain enforces the since of the input, it only has to be at least the lock-up
+    if lock_up != 0 {
+        let since = Since::new(load_input_since(index, Source::Input)?);
+        match since.partial_cmp(&Since::new(lock_up)) {
+            Some(Ordering::Greater | Ordering::Equal) => {}
+            _ => return Err(CKBFSError::DeletionLocked),
+        }
     }
     Ok(())
 }
@@ -268,19 +837,42 @@ pub fn main() -> Result<(), CKBFSError> {
     match (ckbfs_in_input, ckbfs_in_output.len()) {
         (None, 1) => {
             // creation
-            process_creation(ckbfs_in_output[0])?
+            let output_index = ckbfs_in_output[0];
+            match load_ckbfs_data(output_index, Source::Output)? {
+                CKBFSCellData::V2(data) => v2::process_creation(output_index, data)?,
+                CKBFSCellData::V3(_) => process_creation(output_index)?,
+            }
         }
-        (Some(_), 0) => {
-            // destroy, forbidden
-            return Err(CKBFSError::DeletionForbidden);
+        (Some(index), 0) => {
+            // destroy, only for ephemeral files
+            process_destruction(index)?;
         }
         (Some(index), 1) => {
-            // append or transfer
-            process_update(index, ckbfs_in_output[0])?
+            // append or transfer keep the data layout, a v2 cell may also migrate to v3
+            let output_index = ckbfs_in_output[0];
+            match (
+                load_ckbfs_data(index, Source::Input)?,
+                load_ckbfs_data(output_index, Source::Output)?,
+            ) {
+                (CKBFSCellData::V2(input_data), CKBFSCellData::V2(output_data)) => {
+                    v2::process_update(index, output_index, input_data, output_data)?
+                }
+                (CKBFSCellData::V3(_), CKBFSCellData::V3(_)) => {
+                    process_update(index, output_index)?
+                }
+                (CKBFSCellData::V2(input_data), CKBFSCellData::V3(output_data)) => {
+                    v2::process_migration(index, output_index, input_data, output_data)?
+                }
+                _ => return Err(CKBFSError::InvalidFieldUpdate),
+            }
         }
 
         _ => unreachable!(),
     }
 
+    if let Some(&output_index) = ckbfs_in_output.first() {
+        validate_disjoint_witnesses(output_index)?;
+    }
+
     Ok(())
 }
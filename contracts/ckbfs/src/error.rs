@@ -1,6 +1,17 @@
 use ckb_std::error::SysError;
+use ckbfs_witness::WitnessError;
 
 /// Error
+///
+/// Exit codes are grouped in ranges. An i8 ends at 127, so ranges added after the rule errors
+/// grow downwards from 100, ten codes each:
+/// - -1 to -100: syscall errors
+/// - -101 and below: errors reported by the hasher, kept as they are
+/// - 101 to 127: rule errors
+/// - 91 to 100: filename and content_type, checked at creation
+/// - 81 to 90: witnesses
+/// - 71 to 80: concatenation
+/// - 61 to 70: hasher digest
 #[repr(i8)]
 pub enum CKBFSError {
     IndexOutOfBound = -1,
@@ -8,6 +19,23 @@ pub enum CKBFSError {
     LengthNotEnough = -3,
     Encoding = -4,
     Unknown = -100,
+    // errors reported by the hasher, kept as they are, see crates/ckbfs-hasher/src/error.rs
+    WitnessIndexRepeated = -102,
+    WitnessIndexBackward = -103,
+    WitnessIndexOutOfBound = -104,
+    WitnessChainTooLong = -105,
+    InvalidState = -106,
+    Unrecoverable = -107,
+    InvalidArg = -110,
+    InvalidArgLength = -111,
+    MissingArgs = -112,
+    UnknownMode = -113,
+    InvalidSource = -114,
+    InvalidOffset = -115,
+    WitnessMissing = -116,
+    InvalidWitness = -117,
+    InvalidWitnessVersion = -118,
+    InvalidWitnessIndexes = -119,
     InvalidInitialData = 101,
     InvalidTypeId = 102,
     DeletionForbidden = 103,     // we can  not delete a CKBFS cell
@@ -20,6 +48,63 @@ pub enum CKBFSError {
     InvalidWitnessHeader = 110,  // witness header does not match "CKBFS"
     InvalidVersion = 111,        // witness version is not 0x03
     InvalidPreviousPosition = 112, // previous position in witness does not match actual previous output
+    InvalidPreviousChecksum = 113, // previous checksum in witness does not match actual previous checksum
+    HasherFailure = 114,           // hasher failed with a syscall error or an unknown exit code, or reported malformed output
+    InvalidForkSource = 115,       // no CKBFS cell in CellDeps matches the previous position of a fork
+    InvalidBacklinks = 116,        // v2 backlinks are not the input backlinks plus the input position
+    InvalidMigration = 117,        // v2 to v3 migration changed the file, or its head witness is malformed
+    InvalidHasherHashType = 118,   // hasher hash type in Type args is not a valid script hash type
+    FileSealed = 119,              // a sealed file can only be transferred, and can not be unsealed
+    UnauthorizedAppend = 120,      // append without an input locked by one of the appenders
+    UnauthorizedUpdate = 121,      // a file with an owner is transferred, destroyed or changed without the owner
+    ContentHashMismatch = 122,     // content hash does not match the content, or is added / dropped halfway
+    LengthMismatch = 123,          // length does not match the content, or is added / dropped halfway
+    InvalidMetadataUpdate = 124,   // an immutable or append-only metadata entry is changed
+    InvalidTypeArgs = 125,         // unknown file flags, or an invalid lock-up in Type args
+    DeletionLocked = 126,          // an ephemeral file is destroyed before its lock-up
+    InvalidManifest = 127,         // manifest content is not a sequence of valid entries
+    // filename and content_type checked at creation
+    EmptyFilename = 91,
+    InvalidFilenameEncoding = 92,  // filename is not UTF-8
+    InvalidFilenameCharacter = 93, // filename has path separators, NULs or control characters
+    FilenameTooLong = 94,
+    InvalidContentType = 95,       // content_type is not a MIME type
+    ContentTypeTooLong = 96,
+    // witnesses
+    SharedWitness = 81,            // a witness is claimed by more than one CKBFS cell in outputs
+    InvalidWitnessArgs = 82,       // a wrapped witness is not a valid WitnessArgs, or carries no CKBFS payload
+    // concatenation
+    InvalidConcatSource = 71,      // a concatenation source is missing, has another hasher, or B has no length
+    // hasher digest
+    HasherDigestMismatch = 61,     // hasher digest is not the one reported by the hasher, or the hasher resumed from another
+}
+
+// exit code of ckb-adler32 when computed checksum does not match the expected one
+const HASHER_VALIDATE_FAILURE: i8 = -101;
+
+impl CKBFSError {
+    pub fn from_hasher_exit_code(code: i8) -> Self {
+        match code {
+            HASHER_VALIDATE_FAILURE => Self::ChecksumMismatch,
+            -102 => Self::WitnessIndexRepeated,
+            -103 => Self::WitnessIndexBackward,
+            -104 => Self::WitnessIndexOutOfBound,
+            -105 => Self::WitnessChainTooLong,
+            -106 => Self::InvalidState,
+            -107 => Self::Unrecoverable,
+            -110 => Self::InvalidArg,
+            -111 => Self::InvalidArgLength,
+            -112 => Self::MissingArgs,
+            -113 => Self::UnknownMode,
+            -114 => Self::InvalidSource,
+            -115 => Self::InvalidOffset,
+            -116 => Self::WitnessMissing,
+            -117 => Self::InvalidWitness,
+            -118 => Self::InvalidWitnessVersion,
+            -119 => Self::InvalidWitnessIndexes,
+            _ => Self::HasherFailure,
+        }
+    }
 }
 
 impl From<SysError> for CKBFSError {
@@ -34,3 +119,17 @@ impl From<SysError> for CKBFSError {
         }
     }
 }
+
+impl From<WitnessError> for CKBFSError {
+    fn from(err: WitnessError) -> Self {
+        match err {
+            WitnessError::Sys(err) => err.into(),
+            WitnessError::LengthNotEnough => Self::LengthNotEnough,
+            WitnessError::InvalidWitnessArgs => Self::InvalidWitnessArgs,
+            WitnessError::IndexRepeated => Self::WitnessIndexRepeated,
+            WitnessError::IndexBackward => Self::WitnessIndexBackward,
+            WitnessError::IndexOutOfBound => Self::WitnessIndexOutOfBound,
+            WitnessError::ChainTooLong => Self::WitnessChainTooLong,
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use alloc::vec::Vec;
+use ckbfs_types::CKBFSData;
+use molecule::prelude::Entity;
+
+use crate::error::CKBFSError;
+
+// CKBFS v3 cell data is extended by appending fields to the CKBFSData table, readers which only
+// know the first 4 fields still parse it in compatible mode:
+// table CKBFSData {
+//     index, checksum, content_type, filename,
+//     flags: byte, appenders: Byte32Vec, owner: Byte32Opt, content_hash: Byte32Opt, length: Uint64Opt,
+//     metadata: MetadataEntryVec, hasher_digest: Byte32Opt,
+// }
+pub const FLAGS_FIELD_INDEX: usize = 4;
+pub const APPENDERS_FIELD_INDEX: usize = 5;
+pub const OWNER_FIELD_INDEX: usize = 6;
+pub const CONTENT_HASH_FIELD_INDEX: usize = 7;
+pub const LENGTH_FIELD_INDEX: usize = 8;
+pub const METADATA_FIELD_INDEX: usize = 9;
+pub const HASHER_DIGEST_FIELD_INDEX: usize = 10;
+
+// the content is final, no more appends
+pub const FLAG_SEALED: u8 = 0b0000_0001;
+const KNOWN_FLAGS: u8 = FLAG_SEALED;
+
+// returns raw bytes of a field appended to the CKBFSData table, None if the field is absent
+pub fn extra_field(data: &CKBFSData, index: usize) -> Option<&[u8]> {
+    if index >= data.field_count() {
+        return None;
+    }
+    let slice = data.as_slice();
+    let offset_at = |i: usize| u32::from_le_bytes(slice[4 + i * 4..8 + i * 4].try_into().unwrap()) as usize;
+    let start = offset_at(index);
+    let end = if index + 1 == data.field_count() {
+        slice.len()
+    } else {
+        offset_at(index + 1)
+    };
+    Some(&slice[start..end])
+}
+
+// flags default to 0 for cells created before the field was introduced
+pub fn load_flags(data: &CKBFSData) -> Result<u8, CKBFSError> {
+    match extra_field(data, FLAGS_FIELD_INDEX) {
+        None => Ok(0),
+        Some([flags]) if flags & !KNOWN_FLAGS == 0 => Ok(*flags),
+        Some(_) => Err(CKBFSError::Encoding),
+    }
+}
+
+pub fn is_sealed(data: &CKBFSData) -> Result<bool, CKBFSError> {
+    Ok(load_flags(data)? & FLAG_SEALED != 0)
+}
+
+// lock hashes allowed to append, empty if anyone unlocking the cell can append
+pub fn load_appenders(data: &CKBFSData) -> Result<Vec<[u8; 32]>, CKBFSError> {
+    let field = match extra_field(data, APPENDERS_FIELD_INDEX) {
+        None => return Ok(Vec::new()),
+        Some(field) => field,
+    };
+
+    // Byte32Vec: item count + items
+    if field.len() < 4 {
+        return Err(CKBFSError::Encoding);
+    }
+    let count = u32::from_le_bytes(field[0..4].try_into().unwrap()) as usize;
+    let items = &field[4..];
+    if items.len() != count * 32 {
+        return Err(CKBFSError::Encoding);
+    }
+    Ok(items.chunks_exact(32).map(|item| item.try_into().unwrap()).collect())
+}
+
+fn load_byte32_opt(data: &CKBFSData, index: usize) -> Result<Option<[u8; 32]>, CKBFSError> {
+    match extra_field(data, index) {
+        None | Some([]) => Ok(None),
+        Some(field) => field
+            .try_into()
+            .map(Some)
+            .map_err(|_| CKBFSError::Encoding),
+    }
+}
+
+// blake2b hash of the whole content, chained on every append
+pub fn load_content_hash(data: &CKBFSData) -> Result<Option<[u8; 32]>, CKBFSError> {
+    load_byte32_opt(data, CONTENT_HASH_FIELD_INDEX)
+}
+
+// lock hash controlling the file in place of its cell lock, which can then be left open to appenders
+pub fn load_owner(data: &CKBFSData) -> Result<Option<[u8; 32]>, CKBFSError> {
+    load_byte32_opt(data, OWNER_FIELD_INDEX)
+}
+
+// full digest of the content reported by a hasher which has one, its checksum is cut from it
+pub fn load_hasher_digest(data: &CKBFSData) -> Result<Option<[u8; 32]>, CKBFSError> {
+    load_byte32_opt(data, HASHER_DIGEST_FIELD_INDEX)
+}
+
+// total content length in bytes
+pub fn load_length(data: &CKBFSData) -> Result<Option<u64>, CKBFSError> {
+    match extra_field(data, LENGTH_FIELD_INDEX) {
+        None | Some([]) => Ok(None),
+        Some(field) => field
+            .try_into()
+            .map(|length| Some(u64::from_le_bytes(length)))
+            .map_err(|_| CKBFSError::Encoding),
+    }
+}
+
+// optional fields which follow the content of a file, they are checked by walking the witnesses
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub struct ContentDigest {
+    pub hash: Option<[u8; 32]>,
+    pub length: Option<u64>,
+}
+
+pub fn load_content_digest(data: &CKBFSData) -> Result<ContentDigest, CKBFSError> {
+    Ok(ContentDigest {
+        hash: load_content_hash(data)?,
+        length: load_length(data)?,
+    })
+}
+
+// metadata policies, the value of a mutable entry can be changed or removed in any update
+pub const METADATA_MUTABLE: u8 = 0;
+// the entry can never be changed or removed
+pub const METADATA_IMMUTABLE: u8 = 1;
+// the value can only be extended, e.g. a list of authors
+pub const METADATA_APPEND_ONLY: u8 = 2;
+
+// table MetadataEntry { key: Bytes, value: Bytes, policy: byte }
+pub struct MetadataEntry<'a> {
+    pub key: &'a [u8],
+    pub value: &'a [u8],
+    pub policy: u8,
+}
+
+// splits a molecule table or dynvec into its items
+fn dynamic_items(slice: &[u8]) -> Result<Vec<&[u8]>, CKBFSError> {
+    let read_u32 = |offset: usize| -> Result<usize, CKBFSError> {
+        slice
+            .get(offset..offset + 4)
+            .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()) as usize)
+            .ok_or(CKBFSError::Encoding)
+    };
+    if read_u32(0)? != slice.len() {
+        return Err(CKBFSError::Encoding);
+    }
+    if slice.len() == 4 {
+        return Ok(Vec::new());
+    }
+
+    let header_size = read_u32(4)?;
+    if header_size < 8 || header_size % 4 != 0 || header_size > slice.len() {
+        return Err(CKBFSError::Encoding);
+    }
+    let count = header_size / 4 - 1;
+    let mut items = Vec::with_capacity(count);
+    for i in 0..count {
+        let start = read_u32(4 + i * 4)?;
+        let end = if i + 1 == count { slice.len() } else { read_u32(8 + i * 4)? };
+        if start < header_size || start > end || end > slice.len() {
+            return Err(CKBFSError::Encoding);
+        }
+        items.push(&slice[start..end]);
+    }
+    Ok(items)
+}
+
+// Bytes: byte count + bytes
+fn unpack_bytes(slice: &[u8]) -> Result<&[u8], CKBFSError> {
+    if slice.len() < 4 || u32::from_le_bytes(slice[0..4].try_into().unwrap()) as usize != slice.len() - 4 {
+        return Err(CKBFSError::Encoding);
+    }
+    Ok(&slice[4..])
+}
+
+// descriptive metadata like license or author, keys must be sorted and unique
+pub fn load_metadata(data: &CKBFSData) -> Result<Vec<MetadataEntry<'_>>, CKBFSError> {
+    let field = match extra_field(data, METADATA_FIELD_INDEX) {
+        None => return Ok(Vec::new()),
+        Some(field) => field,
+    };
+
+    let mut entries: Vec<MetadataEntry> = Vec::new();
+    for item in dynamic_items(field)? {
+        let entry = match dynamic_items(item)?.as_slice() {
+            [key, value, [policy]] if *policy <= METADATA_APPEND_ONLY => MetadataEntry {
+                key: unpack_bytes(key)?,
+                value: unpack_bytes(value)?,
+                policy: *policy,
+            },
+            _ => return Err(CKBFSError::Encoding),
+        };
+        if entries.last().is_some_and(|last| last.key >= entry.key) {
+            return Err(CKBFSError::Encoding);
+        }
+        entries.push(entry);
+    }
+    Ok(entries)
+}
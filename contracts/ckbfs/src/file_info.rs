@@ -0,0 +1,75 @@
+use crate::error::CKBFSError;
+
+pub const MAX_FILENAME_LENGTH: usize = 255;
+pub const MAX_CONTENT_TYPE_LENGTH: usize = 255;
+
+// Rule 33: filename is non-empty UTF-8 with no path separators, NULs or control characters
+pub fn validate_filename(filename: &[u8]) -> Result<(), CKBFSError> {
+    if filename.is_empty() {
+        return Err(CKBFSError::EmptyFilename);
+    }
+    if filename.len() > MAX_FILENAME_LENGTH {
+        return Err(CKBFSError::FilenameTooLong);
+    }
+    let filename = core::str::from_utf8(filename).map_err(|_| CKBFSError::InvalidFilenameEncoding)?;
+    if filename == "."
+        || filename == ".."
+        || filename
+            .chars()
+            .any(|c| c == '/' || c == '\\' || c.is_control())
+    {
+        return Err(CKBFSError::InvalidFilenameCharacter);
+    }
+    Ok(())
+}
+
+// token characters of RFC 2045: visible ASCII except tspecials
+fn is_token_char(c: u8) -> bool {
+    c.is_ascii_graphic() && !b"()<>@,;:\\\"/[]?=".contains(&c)
+}
+
+// consumes a token, returns the rest
+fn token(input: &[u8]) -> Option<&[u8]> {
+    let len = input.iter().take_while(|c| is_token_char(**c)).count();
+    (len > 0).then(|| &input[len..])
+}
+
+// consumes a quoted-string, returns the rest
+fn quoted_string(input: &[u8]) -> Option<&[u8]> {
+    let mut rest = input.strip_prefix(b"\"")?;
+    loop {
+        match rest {
+            [b'"', tail @ ..] => return Some(tail),
+            [b'\\', c, tail @ ..] if *c == b'\t' || (b' '..=b'~').contains(c) => rest = tail,
+            [c, tail @ ..] if *c == b'\t' || ((b' '..=b'~').contains(c) && *c != b'\\') => rest = tail,
+            _ => return None,
+        }
+    }
+}
+
+fn skip_spaces(input: &[u8]) -> &[u8] {
+    let len = input.iter().take_while(|c| **c == b' ' || **c == b'\t').count();
+    &input[len..]
+}
+
+// Rule 34: content_type is a MIME type: type "/" subtype *(";" parameter)
+pub fn validate_content_type(content_type: &[u8]) -> Result<(), CKBFSError> {
+    if content_type.len() > MAX_CONTENT_TYPE_LENGTH {
+        return Err(CKBFSError::ContentTypeTooLong);
+    }
+
+    let mut rest = token(content_type)
+        .and_then(|rest| rest.strip_prefix(b"/"))
+        .and_then(token)
+        .ok_or(CKBFSError::InvalidContentType)?;
+    while !rest.is_empty() {
+        rest = skip_spaces(rest)
+            .strip_prefix(b";")
+            .map(skip_spaces)
+            .and_then(token)
+            .and_then(|rest| rest.strip_prefix(b"="))
+            .and_then(|rest| token(rest).or_else(|| quoted_string(rest)))
+            .ok_or(CKBFSError::InvalidContentType)?;
+    }
+    Ok(())
+}
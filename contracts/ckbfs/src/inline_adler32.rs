@@ -0,0 +1,42 @@
+use ckbfs_adler32::Adler32;
+use ckbfs_hasher::{hash_chain, hash_witnesses, HasherError, Report};
+
+use crate::entry::{HasherScript, DEFAULT_HASHER};
+use crate::error::CKBFSError;
+
+// The default hasher is computed in-process instead of spawning ckb-adler32, by the same modes of
+// ckbfs-hasher, and failing with the same errors. Custom hashers are still spawned.
+pub fn is_inline_hasher(hasher: Option<HasherScript>) -> bool {
+    hasher.unwrap_or(DEFAULT_HASHER) == DEFAULT_HASHER
+}
+
+// fails with the exit code ckb-adler32 would exit with, mapped the same way as a spawned hasher's
+fn compare_checksum(result: Result<Report, HasherError>, checksum: u32) -> Result<(), CKBFSError> {
+    let report = result.map_err(|err| CKBFSError::from_hasher_exit_code(err as i8))?;
+    if report.checksum != checksum {
+        return Err(CKBFSError::ChecksumMismatch);
+    }
+    Ok(())
+}
+
+// mode 3 of ckb-adler32
+pub fn validate_v3(witness_index: u32, checksum: u32, recover: Option<u32>) -> Result<(), CKBFSError> {
+    compare_checksum(hash_chain::<Adler32>(witness_index as usize, recover), checksum)
+}
+
+// mode 1 of ckb-adler32
+pub fn validate_v2(
+    indexes: impl IntoIterator<Item = usize>,
+    checksum: u32,
+    recover: Option<u32>,
+) -> Result<(), CKBFSError> {
+    compare_checksum(hash_witnesses::<Adler32>(indexes, recover), checksum)
+}
+
+// mode 4 of ckb-adler32
+pub fn validate_combine(checksum_a: u32, checksum_b: u32, length_b: u64, checksum: u32) -> Result<(), CKBFSError> {
+    if ckbfs_adler32::combine(checksum_a, checksum_b, length_b) != checksum {
+        return Err(CKBFSError::ChecksumMismatch);
+    }
+    Ok(())
+}
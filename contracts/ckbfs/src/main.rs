@@ -11,9 +11,16 @@ use ckb_std::default_alloc;
 ckb_std::entry!(program_entry);
 #[cfg(not(test))]
 default_alloc!();
+mod concat;
 mod entry;
 mod error;
+mod fields;
+mod file_info;
 mod hash;
+#[cfg(feature = "inline-adler32")]
+mod inline_adler32;
+mod manifest;
+mod v2;
 
 pub fn program_entry() -> i8 {
     match entry::main() {
@@ -0,0 +1,22 @@
+use alloc::vec::Vec;
+use ckb_std::ckb_constants::Source;
+use ckbfs_manifest::{parse_entries, MANIFEST_CONTENT_TYPE};
+use ckbfs_types::CKBFSData;
+use ckbfs_witness::stream_chain_content;
+
+use crate::error::CKBFSError;
+
+// Rule 31: content of a manifest, as well as content appended to it, is a sequence of whole entries
+pub fn validate_manifest_content(data: &CKBFSData, witness_index: u32) -> Result<(), CKBFSError> {
+    if data.content_type().raw_data()[..] != *MANIFEST_CONTENT_TYPE {
+        return Ok(());
+    }
+
+    // entries are small, and can span witnesses, so the content is collected first
+    let mut content = Vec::new();
+    stream_chain_content(witness_index as usize, Source::Output, |part| {
+        content.extend_from_slice(part)
+    })?;
+    parse_entries(&content).map_err(|_| CKBFSError::InvalidManifest)?;
+    Ok(())
+}
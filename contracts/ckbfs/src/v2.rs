@@ -0,0 +1,253 @@
+use alloc::vec::Vec;
+use ckb_std::{
+    ckb_constants::Source,
+    ckb_types::prelude::Entity as _,
+    error::SysError,
+    high_level::load_input_out_point,
+    syscalls,
+};
+use ckbfs_types::CKBFSData as CKBFSDataV3;
+use ckbfs_types_v2::{
+    generated::ckbfs::{BackLink, BackLinkVec, Byte32, Indexes, Uint32},
+    CKBFSData,
+};
+use ckbfs_witness::load_head_witness_payload;
+use molecule::prelude::{Builder, Entity};
+
+use crate::entry::{
+    encode_hex_0x, load_type_args, parse_head_witness, unpack_ephemeral_args, unpack_type_args,
+    validate_by_spawn, validate_head_witness, validate_type_id, HasherScript, CKBFS_HEADER,
+    CKBFS_V3_HEAD_WITNESS_OFFSET,
+};
+use crate::error::CKBFSError;
+use crate::fields::{
+    load_appenders, load_content_digest, load_flags, load_hasher_digest, load_metadata, load_owner,
+};
+use crate::file_info::{validate_content_type, validate_filename};
+
+pub const CKBFS_VERSION_V2: u8 = 0x00;
+
+// hasher mode for v2 witnesses, args should be: [WITNESSES_INDEX, EXPECT_CHECKSUM, RECOVER_CHECKSUM(OPTIONAL)]
+const HASHER_MODE_V2: u8 = 1;
+
+fn unpack_u32(value: &Uint32) -> u32 {
+    u32::from_le_bytes(value.as_slice().try_into().unwrap())
+}
+
+// Rule 6, Rule 7: every witness referenced by indexes is "CKBFS" + 0x00 + content
+fn validate_witnesses(indexes: &Indexes) -> Result<(), CKBFSError> {
+    for index in indexes.clone().into_iter() {
+        // only the header is needed here, the content is left to the hasher
+        let mut header = [0u8; 6];
+        match syscalls::load_witness(&mut header, 0, unpack_u32(&index) as usize, Source::Output) {
+            Ok(len) | Err(SysError::LengthNotEnough(len)) if len >= header.len() => {}
+            Ok(_) | Err(SysError::LengthNotEnough(_)) => return Err(CKBFSError::InvalidWitnessHeader),
+            Err(err) => return Err(err.into()),
+        }
+
+        if header[0..5] != CKBFS_HEADER {
+            return Err(CKBFSError::InvalidWitnessHeader);
+        }
+        if header[5] != CKBFS_VERSION_V2 {
+            return Err(CKBFSError::InvalidVersion);
+        }
+    }
+    Ok(())
+}
+
+pub fn claimed_witnesses(data: &CKBFSData) -> Vec<usize> {
+    data.indexes()
+        .into_iter()
+        .map(|index| unpack_u32(&index) as usize)
+        .collect()
+}
+
+fn validate_by_spawn_v2(
+    indexes: &Indexes,
+    checksum: u32,
+    recover: Option<u32>,
+    hasher: Option<HasherScript>,
+) -> Result<(), CKBFSError> {
+    #[cfg(feature = "inline-adler32")]
+    if crate::inline_adler32::is_inline_hasher(hasher) {
+        let indexes = indexes.clone().into_iter().map(|index| unpack_u32(&index) as usize);
+        return crate::inline_adler32::validate_v2(indexes, checksum, recover);
+    }
+
+    // v2 cell data has no hasher digest, and a v2 file is never resumed from a hasher state
+    let indexes_arg = encode_hex_0x(indexes.as_slice());
+    validate_by_spawn(HASHER_MODE_V2, &indexes_arg, checksum, recover, hasher).map(|_| ())
+}
+
+// Rule 10, Rule 15: existing backlinks are kept as they are, and if the input cell carries
+// content itself, it is linked as the last backlink so its position is never lost
+fn expected_backlinks(input_index: usize, input_data: &CKBFSData) -> Result<BackLinkVec, CKBFSError> {
+    let previous_output = load_input_out_point(input_index, Source::Input)?;
+    let mut backlinks = BackLinkVec::new_builder().extend(input_data.backlinks());
+    if !input_data.indexes().is_empty() {
+        let tx_hash = Byte32::from_slice(previous_output.tx_hash().as_slice())
+            .map_err(|_| CKBFSError::Encoding)?;
+        backlinks = backlinks.push(
+            BackLink::new_builder()
+                .indexes(input_data.indexes())
+                .checksum(input_data.checksum())
+                .tx_hash(tx_hash)
+                .build(),
+        );
+    }
+    Ok(backlinks.build())
+}
+
+pub fn process_creation(index: usize, data: CKBFSData) -> Result<(), CKBFSError> {
+    let type_script_args = load_type_args(index, Source::Output);
+    let (type_id, checksum_hasher) = unpack_type_args(&type_script_args)?;
+    unpack_ephemeral_args(&type_script_args)?;
+
+    // validate unique id
+    if !validate_type_id(&type_id, index) {
+        return Err(CKBFSError::InvalidTypeId);
+    }
+
+    validate_filename(&data.filename().raw_data())?;
+    validate_content_type(&data.content_type().raw_data())?;
+
+    // a published file has content, and no history yet
+    if data.indexes().is_empty() {
+        return Err(CKBFSError::InvalidInitialData);
+    }
+    if !data.backlinks().is_empty() {
+        return Err(CKBFSError::InvalidBacklinks);
+    }
+
+    // Rule 9: checksum must be equal with hash(Witnesses[indexes])
+    validate_witnesses(&data.indexes())?;
+    validate_by_spawn_v2(
+        &data.indexes(),
+        unpack_u32(&data.checksum()),
+        None,
+        checksum_hasher,
+    )
+}
+
+pub fn process_update(
+    input_index: usize,
+    output_index: usize,
+    input_data: CKBFSData,
+    output_data: CKBFSData,
+) -> Result<(), CKBFSError> {
+    // Rule 12: content-type, filename, and Type args cannot be changed
+    if input_data.content_type().as_slice() != output_data.content_type().as_slice() {
+        return Err(CKBFSError::InvalidFieldUpdate);
+    }
+
+    if input_data.filename().as_slice() != output_data.filename().as_slice() {
+        return Err(CKBFSError::InvalidFieldUpdate);
+    }
+
+    let input_type_args = load_type_args(input_index, Source::Input);
+    let output_type_args = load_type_args(output_index, Source::Output);
+    if input_type_args != output_type_args {
+        return Err(CKBFSError::InvalidFieldUpdate);
+    }
+
+    if output_data.backlinks().as_slice() != expected_backlinks(input_index, &input_data)?.as_slice() {
+        return Err(CKBFSError::InvalidBacklinks);
+    }
+
+    let (_, checksum_hasher) = unpack_type_args(&output_type_args)?;
+    let input_checksum = unpack_u32(&input_data.checksum());
+    let output_checksum = unpack_u32(&output_data.checksum());
+
+    if input_checksum == output_checksum {
+        // Transfer operation: Rule 16 - checksum cannot be updated
+        // Rule 14: indexes must be empty
+        if !output_data.indexes().is_empty() {
+            return Err(CKBFSError::InvalidTransfer);
+        }
+        return Ok(());
+    }
+
+    // Append operation: Rule 13 - indexes can not be empty
+    if output_data.indexes().is_empty() {
+        return Err(CKBFSError::InvalidAppend);
+    }
+
+    // Rule 11: new checksum should be hasher.recover_from(old_checksum).update(new_content_bytes)
+    validate_witnesses(&output_data.indexes())?;
+    validate_by_spawn_v2(
+        &output_data.indexes(),
+        output_checksum,
+        Some(input_checksum),
+        checksum_hasher,
+    )
+}
+
+// Migration turns a v2 cell into a v3 cell, the backlinks move out of cell data into the head witness:
+// <"CKBFS"> <0x03> <LATEST_POSITION> <CHECKSUM> <0x00000000> <BackLinkVec of the full history>
+pub fn process_migration(
+    input_index: usize,
+    output_index: usize,
+    input_data: CKBFSData,
+    output_data: CKBFSDataV3,
+) -> Result<(), CKBFSError> {
+    // the file itself can not be changed: content-type, filename, Type args and checksum
+    if input_data.content_type().as_slice() != output_data.content_type().as_slice()
+        || input_data.filename().as_slice() != output_data.filename().as_slice()
+    {
+        return Err(CKBFSError::InvalidFieldUpdate);
+    }
+
+    let input_type_args = load_type_args(input_index, Source::Input);
+    let output_type_args = load_type_args(output_index, Source::Output);
+    if input_type_args != output_type_args {
+        return Err(CKBFSError::InvalidFieldUpdate);
+    }
+
+    // a file can be sealed, name its appenders and owner, or carry metadata while migrating
+    load_flags(&output_data)?;
+    load_appenders(&output_data)?;
+    load_owner(&output_data)?;
+    load_metadata(&output_data)?;
+
+    // v2 files have no content hash, length or hasher digest to carry on
+    if load_hasher_digest(&output_data)?.is_some() {
+        return Err(CKBFSError::HasherDigestMismatch);
+    }
+    let digest = load_content_digest(&output_data)?;
+    if digest.hash.is_some() {
+        return Err(CKBFSError::ContentHashMismatch);
+    }
+    if digest.length.is_some() {
+        return Err(CKBFSError::LengthMismatch);
+    }
+
+    let checksum = unpack_u32(&input_data.checksum());
+    if checksum != u32::from_le_bytes(output_data.checksum().as_slice().try_into().unwrap()) {
+        return Err(CKBFSError::InvalidMigration);
+    }
+
+    let backlinks = expected_backlinks(input_index, &input_data)?;
+    let latest = backlinks.get(backlinks.len().wrapping_sub(1)).ok_or(CKBFSError::InvalidMigration)?;
+    let latest_index = latest.indexes().get(0).ok_or(CKBFSError::InvalidMigration)?;
+
+    // the head witness points to where the latest content was stored, and carries no content
+    let witness_index = u32::from_le_bytes(output_data.index().as_slice().try_into().unwrap());
+    let head_witness = load_head_witness_payload(witness_index as usize, Source::Output)?;
+    let parsed_head_witness = parse_head_witness(&head_witness)?;
+    validate_head_witness(
+        &parsed_head_witness,
+        latest.tx_hash().as_slice(),
+        unpack_u32(&latest_index),
+        checksum,
+    )?;
+    if parsed_head_witness.next_index != 0 {
+        return Err(CKBFSError::InvalidMigration);
+    }
+
+    // the whole history is kept in the head witness
+    if head_witness[CKBFS_V3_HEAD_WITNESS_OFFSET..] != *backlinks.as_slice() {
+        return Err(CKBFSError::InvalidBacklinks);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,55 @@
+#![no_std]
+
+use ckbfs_hasher::Hasher;
+
+const ADLER32_MOD: u32 = 65521;
+
+/// Adler32, the default CKBFS checksum, as a hasher of `ckbfs-hasher`. `ckb-adler32` runs it as a
+/// spawned hasher, and the `ckbfs` contract runs it in-process with the `inline-adler32` feature.
+pub struct Adler32(adler::Adler32);
+
+impl Hasher for Adler32 {
+    const NAME: &'static str = "CKB-Adler32";
+
+    fn new() -> Self {
+        Self(adler::Adler32::new())
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        self.0.write_slice(data);
+    }
+
+    fn checksum(&self) -> u32 {
+        self.0.checksum()
+    }
+
+    fn recover(checksum: u32) -> Option<Self> {
+        Some(Self(adler::Adler32::from_checksum(checksum)))
+    }
+
+    fn combine(checksum_a: u32, checksum_b: u32, length_b: u64) -> Option<u32> {
+        Some(combine(checksum_a, checksum_b, length_b))
+    }
+}
+
+// Adler32 of A || B from the Adler32 of A and B, and the length of B, same as adler32_combine of zlib
+pub fn combine(checksum_a: u32, checksum_b: u32, length_b: u64) -> u32 {
+    let rem = (length_b % ADLER32_MOD as u64) as u32;
+    let mut sum1 = checksum_a & 0xffff;
+    let mut sum2 = (rem * sum1) % ADLER32_MOD;
+    sum1 += (checksum_b & 0xffff) + ADLER32_MOD - 1;
+    sum2 += (checksum_a >> 16) + (checksum_b >> 16) + ADLER32_MOD - rem;
+    if sum1 >= ADLER32_MOD {
+        sum1 -= ADLER32_MOD;
+    }
+    if sum1 >= ADLER32_MOD {
+        sum1 -= ADLER32_MOD;
+    }
+    if sum2 >= ADLER32_MOD << 1 {
+        sum2 -= ADLER32_MOD << 1;
+    }
+    if sum2 >= ADLER32_MOD {
+        sum2 -= ADLER32_MOD;
+    }
+    sum1 | (sum2 << 16)
+}
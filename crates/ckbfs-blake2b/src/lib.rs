@@ -0,0 +1,214 @@
+#![no_std]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+// same parameters as ckb_hash::blake2b_256
+pub const DIGEST_SIZE: usize = 32;
+pub const PERSONALIZATION: &[u8; 16] = b"ckb-default-hash";
+
+pub const BLOCK_SIZE: usize = 128;
+// Serialized state: chaining value(64) + compressed byte count(16) + buffered bytes(0..=128)
+pub const STATE_HEADER_SIZE: usize = 64 + 16;
+pub const MAX_STATE_SIZE: usize = STATE_HEADER_SIZE + BLOCK_SIZE;
+
+const IV: [u64; 8] = [
+    0x6a09e667f3bcc908,
+    0xbb67ae8584caa73b,
+    0x3c6ef372fe94f82b,
+    0xa54ff53a5f1d36f1,
+    0x510e527fade682d1,
+    0x9b05688c2b3e6c1f,
+    0x1f83d9abfb41bd6b,
+    0x5be0cd19137e2179,
+];
+
+const SIGMA: [[usize; 16]; 12] = [
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+    [14, 10, 4, 8, 9, 15, 13, 6, 1, 12, 0, 2, 11, 7, 5, 3],
+    [11, 8, 12, 0, 5, 2, 15, 13, 10, 14, 3, 6, 7, 1, 9, 4],
+    [7, 9, 3, 1, 13, 12, 11, 14, 2, 6, 5, 10, 4, 0, 15, 8],
+    [9, 0, 5, 7, 2, 4, 10, 15, 14, 1, 11, 12, 6, 8, 3, 13],
+    [2, 12, 6, 10, 0, 11, 8, 3, 4, 13, 7, 5, 15, 14, 1, 9],
+    [12, 5, 1, 15, 14, 13, 4, 10, 0, 7, 6, 3, 9, 2, 8, 11],
+    [13, 11, 7, 14, 12, 1, 3, 9, 5, 0, 15, 4, 8, 6, 2, 10],
+    [6, 15, 14, 9, 11, 3, 0, 8, 12, 2, 13, 7, 1, 4, 10, 5],
+    [10, 2, 8, 4, 7, 6, 1, 5, 15, 11, 9, 14, 3, 12, 13, 0],
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+    [14, 10, 4, 8, 9, 15, 13, 6, 1, 12, 0, 2, 11, 7, 5, 3],
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    // serialized state is malformed
+    InvalidState,
+}
+
+// A blake2b-256 hasher that can be stopped and resumed from its serialized state. Like the
+// reference implementation, the last block is kept in the buffer until more content arrives,
+// since it is compressed differently when the hash is finalized.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Blake2b {
+    h: [u64; 8],
+    counter: u128,
+    buf: [u8; BLOCK_SIZE],
+    buf_len: usize,
+}
+
+impl Default for Blake2b {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Blake2b {
+    pub fn new() -> Self {
+        Self::with_params(DIGEST_SIZE as u8, PERSONALIZATION)
+    }
+
+    // unkeyed blake2b of `digest_size` bytes, e.g. 64 and no personalization for plain BLAKE2b-512
+    pub fn with_params(digest_size: u8, personalization: &[u8; 16]) -> Self {
+        let mut h = IV;
+        // parameter block: digest length, key length 0, fanout 1, depth 1, personalization
+        h[0] ^= 0x0101_0000 | digest_size as u64;
+        h[6] ^= u64::from_le_bytes(personalization[0..8].try_into().unwrap());
+        h[7] ^= u64::from_le_bytes(personalization[8..16].try_into().unwrap());
+        Self {
+            h,
+            counter: 0,
+            buf: [0u8; BLOCK_SIZE],
+            buf_len: 0,
+        }
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        if data.is_empty() {
+            return;
+        }
+
+        let fill = BLOCK_SIZE - self.buf_len;
+        if data.len() > fill {
+            self.buf[self.buf_len..].copy_from_slice(&data[..fill]);
+            self.counter += BLOCK_SIZE as u128;
+            let block = self.buf;
+            compress(&mut self.h, &block, self.counter, false);
+            self.buf_len = 0;
+            data = &data[fill..];
+
+            while data.len() > BLOCK_SIZE {
+                self.counter += BLOCK_SIZE as u128;
+                compress(&mut self.h, data[..BLOCK_SIZE].try_into().unwrap(), self.counter, false);
+                data = &data[BLOCK_SIZE..];
+            }
+        }
+
+        self.buf[self.buf_len..self.buf_len + data.len()].copy_from_slice(data);
+        self.buf_len += data.len();
+    }
+
+    // the hasher is left as it is, so hashing can go on afterwards
+    pub fn finalize(&self) -> [u8; DIGEST_SIZE] {
+        self.finalize_full()[..DIGEST_SIZE].try_into().unwrap()
+    }
+
+    // all 64 bytes of the final chaining value, a digest is its first `digest_size` bytes
+    pub fn finalize_full(&self) -> [u8; 64] {
+        let mut h = self.h;
+        let mut block = [0u8; BLOCK_SIZE];
+        block[..self.buf_len].copy_from_slice(&self.buf[..self.buf_len]);
+        compress(&mut h, &block, self.counter + self.buf_len as u128, true);
+
+        let mut digest = [0u8; 64];
+        for (chunk, word) in digest.chunks_exact_mut(8).zip(h.iter()) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        digest
+    }
+
+    // the CKBFS checksum is the first 4 bytes of the digest, as a little endian u32
+    pub fn checksum(&self) -> u32 {
+        u32::from_le_bytes(self.finalize()[..4].try_into().unwrap())
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut state = Vec::with_capacity(STATE_HEADER_SIZE + self.buf_len);
+        for word in self.h.iter() {
+            state.extend_from_slice(&word.to_le_bytes());
+        }
+        state.extend_from_slice(&self.counter.to_le_bytes());
+        state.extend_from_slice(&self.buf[..self.buf_len]);
+        state
+    }
+
+    // Only the shape of the state is checked, any chaining value is taken as it is. A state can not
+    // be told apart from one reached by hashing content, it is bound to the previous content by
+    // the hasher_digest the CKBFS contract keeps on-chain, which the resumed state must digest to.
+    pub fn deserialize(state: &[u8]) -> Result<Self, Error> {
+        if state.len() < STATE_HEADER_SIZE || state.len() > MAX_STATE_SIZE {
+            return Err(Error::InvalidState);
+        }
+
+        let mut h = [0u64; 8];
+        for (word, chunk) in h.iter_mut().zip(state[..64].chunks_exact(8)) {
+            *word = u64::from_le_bytes(chunk.try_into().unwrap());
+        }
+        let counter = u128::from_le_bytes(state[64..STATE_HEADER_SIZE].try_into().unwrap());
+        let buffered = &state[STATE_HEADER_SIZE..];
+        // only whole blocks are compressed, and never the last one
+        if counter % BLOCK_SIZE as u128 != 0 || (counter != 0 && buffered.is_empty()) {
+            return Err(Error::InvalidState);
+        }
+
+        let mut buf = [0u8; BLOCK_SIZE];
+        buf[..buffered.len()].copy_from_slice(buffered);
+        Ok(Self {
+            h,
+            counter,
+            buf,
+            buf_len: buffered.len(),
+        })
+    }
+}
+
+fn compress(h: &mut [u64; 8], block: &[u8; BLOCK_SIZE], counter: u128, last: bool) {
+    let mut m = [0u64; 16];
+    for (word, chunk) in m.iter_mut().zip(block.chunks_exact(8)) {
+        *word = u64::from_le_bytes(chunk.try_into().unwrap());
+    }
+
+    let mut v = [0u64; 16];
+    v[..8].copy_from_slice(h);
+    v[8..].copy_from_slice(&IV);
+    v[12] ^= counter as u64;
+    v[13] ^= (counter >> 64) as u64;
+    if last {
+        v[14] = !v[14];
+    }
+
+    for s in SIGMA.iter() {
+        g(&mut v, 0, 4, 8, 12, m[s[0]], m[s[1]]);
+        g(&mut v, 1, 5, 9, 13, m[s[2]], m[s[3]]);
+        g(&mut v, 2, 6, 10, 14, m[s[4]], m[s[5]]);
+        g(&mut v, 3, 7, 11, 15, m[s[6]], m[s[7]]);
+        g(&mut v, 0, 5, 10, 15, m[s[8]], m[s[9]]);
+        g(&mut v, 1, 6, 11, 12, m[s[10]], m[s[11]]);
+        g(&mut v, 2, 7, 8, 13, m[s[12]], m[s[13]]);
+        g(&mut v, 3, 4, 9, 14, m[s[14]], m[s[15]]);
+    }
+
+    for i in 0..8 {
+        h[i] ^= v[i] ^ v[i + 8];
+    }
+}
+
+fn g(v: &mut [u64; 16], a: usize, b: usize, c: usize, d: usize, x: u64, y: u64) {
+    v[a] = v[a].wrapping_add(v[b]).wrapping_add(x);
+    v[d] = (v[d] ^ v[a]).rotate_right(32);
+    v[c] = v[c].wrapping_add(v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(24);
+    v[a] = v[a].wrapping_add(v[b]).wrapping_add(y);
+    v[d] = (v[d] ^ v[a]).rotate_right(16);
+    v[c] = v[c].wrapping_add(v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(63);
+}
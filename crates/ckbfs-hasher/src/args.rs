@@ -0,0 +1,91 @@
+use alloc::{vec, vec::Vec};
+use ckb_std::{ckb_constants::Source, env::Arg};
+
+use crate::error::HasherError;
+
+// a number arg, either decimal or little endian hex with 0x prefix
+pub trait NumArg: Sized {
+    fn parse(arg: &Arg) -> Result<Self, HasherError>;
+}
+
+macro_rules! impl_num_arg {
+    ($($type:ty),*) => {$(
+        impl NumArg for $type {
+            fn parse(arg: &Arg) -> Result<Self, HasherError> {
+                let arg_str = arg.to_str().map_err(|_| HasherError::InvalidArg)?;
+                match arg_str.strip_prefix("0x") {
+                    Some(hex_str) if hex_str.len() != core::mem::size_of::<$type>() * 2 => {
+                        Err(HasherError::InvalidArgLength)
+                    }
+                    Some(hex_str) => {
+                        let mut buffer = [0u8; core::mem::size_of::<$type>()];
+                        faster_hex::hex_decode(hex_str.as_bytes(), &mut buffer)
+                            .map_err(|_| HasherError::InvalidArg)?;
+                        Ok(<$type>::from_le_bytes(buffer))
+                    }
+                    None => arg_str.parse::<$type>().map_err(|_| HasherError::InvalidArg),
+                }
+            }
+        }
+    )*};
+}
+
+impl_num_arg!(u8, u32, u64);
+
+// args of a mode, the mode itself included
+pub struct Args<'a>(pub &'a [Arg]);
+
+impl Args<'_> {
+    // args should have at least `count` entries
+    pub fn require(&self, count: usize) -> Result<(), HasherError> {
+        if self.0.len() < count {
+            ckb_std::debug!("MissingArgs, {} < {}", self.0.len(), count);
+            return Err(HasherError::MissingArgs);
+        }
+        Ok(())
+    }
+
+    pub fn bytes(&self, index: usize) -> Result<&[u8], HasherError> {
+        self.0.get(index).map(|arg| arg.to_bytes()).ok_or(HasherError::MissingArgs)
+    }
+
+    pub fn num<T: NumArg>(&self, index: usize) -> Result<T, HasherError> {
+        T::parse(self.0.get(index).ok_or(HasherError::MissingArgs)?)
+    }
+
+    // a trailing optional number arg, e.g. the recover checksum
+    pub fn optional_num<T: NumArg>(&self, index: usize) -> Result<Option<T>, HasherError> {
+        self.0.get(index).map(T::parse).transpose()
+    }
+
+    // a 0x prefixed hex arg
+    pub fn hex(&self, index: usize) -> Result<Vec<u8>, HasherError> {
+        let arg = self.0.get(index).ok_or(HasherError::MissingArgs)?;
+        let hex_str = arg
+            .to_str()
+            .ok()
+            .and_then(|arg_str| arg_str.strip_prefix("0x"))
+            .ok_or(HasherError::InvalidArg)?;
+        if !hex_str.len().is_multiple_of(2) {
+            return Err(HasherError::InvalidArgLength);
+        }
+        let mut buffer = vec![0u8; hex_str.len() / 2];
+        faster_hex::hex_decode(hex_str.as_bytes(), &mut buffer).map_err(|_| HasherError::InvalidArg)?;
+        Ok(buffer)
+    }
+
+    pub fn source(&self, index: usize) -> Result<Source, HasherError> {
+        match self.num::<u64>(index)? {
+            1 => Ok(Source::Input),
+            2 => Ok(Source::Output),
+            3 => Ok(Source::CellDep),
+            4 => Ok(Source::HeaderDep),
+            0x0100000000000001 => Ok(Source::GroupInput),
+            0x0100000000000002 => Ok(Source::GroupOutput),
+            value => {
+                ckb_std::debug!("{} is not a valid source", value);
+                Err(HasherError::InvalidSource)
+            }
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use ckb_std::error::SysError;
+use ckbfs_witness::WitnessError;
+
+/// Exit codes of a hasher, the CKBFS contract maps them back to its own errors
+#[repr(i8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HasherError {
+    IndexOutOfBound = -1,
+    ItemMissing = -2,
+    LengthNotEnough = -3,
+    Encoding = -4,
+
+    Unknown = -100,
+    ValidateFailure = -101,
+    WitnessIndexRepeated = -102,   // v3 witness chain visits the same witness twice
+    WitnessIndexBackward = -103,   // v3 next index points to an earlier witness
+    WitnessIndexOutOfBound = -104, // v3 next index is beyond the witnesses of the transaction
+    WitnessChainTooLong = -105,    // v3 witness chain exceeds MAX_WITNESS_CHAIN_LENGTH
+    InvalidState = -106,           // v3 head witness carries no valid state, or it does not finalize to the previous checksum
+    Unrecoverable = -107,          // a recover checksum is given, but the hasher can not recover from a checksum
+    InvalidArg = -110,             // arg is not UTF-8, not a decimal number, or not hex
+    InvalidArgLength = -111,       // hex arg does not have the size of the value it encodes
+    MissingArgs = -112,            // fewer args than the mode needs
+    UnknownMode = -113,            // mode is unknown, or not supported by the hasher
+    InvalidSource = -114,          // mode 2 source is not a CKB source
+    InvalidOffset = -115,          // mode 2 offset is beyond the cell data
+    WitnessMissing = -116,         // witness index is beyond the witnesses of the transaction
+    InvalidWitness = -117,         // witness is shorter than its header, or a malformed WitnessArgs
+    InvalidWitnessVersion = -118,  // head witness is not CKBFS v3
+    InvalidWitnessIndexes = -119,  // mode 1 indexes are not a molecule Uint32Vec
+}
+
+impl From<SysError> for HasherError {
+    fn from(err: SysError) -> Self {
+        use SysError::*;
+        match err {
+            IndexOutOfBound => Self::IndexOutOfBound,
+            ItemMissing => Self::ItemMissing,
+            LengthNotEnough(_) => Self::LengthNotEnough,
+            Encoding => Self::Encoding,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+impl From<WitnessError> for HasherError {
+    fn from(err: WitnessError) -> Self {
+        match err {
+            WitnessError::Sys(SysError::IndexOutOfBound) => Self::WitnessMissing,
+            WitnessError::Sys(err) => err.into(),
+            WitnessError::LengthNotEnough | WitnessError::InvalidWitnessArgs => Self::InvalidWitness,
+            WitnessError::IndexRepeated => Self::WitnessIndexRepeated,
+            WitnessError::IndexBackward => Self::WitnessIndexBackward,
+            WitnessError::IndexOutOfBound => Self::WitnessIndexOutOfBound,
+            WitnessError::ChainTooLong => Self::WitnessChainTooLong,
+        }
+    }
+}
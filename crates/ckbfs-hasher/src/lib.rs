@@ -0,0 +1,344 @@
+#![no_std]
+
+extern crate alloc;
+
+mod args;
+mod error;
+
+use alloc::vec::Vec;
+use ckb_std::{
+    ckb_constants::Source,
+    ckb_types::{
+        packed::Uint32Vec,
+        prelude::{Entity, Unpack},
+    },
+    high_level::{encode_hex, inherited_fds, load_cell_data, load_witness_args},
+    syscalls,
+};
+use ckbfs_witness::{
+    is_wrapped_head_witness, load_head_witness_header, load_witness_len, stream_chain_content,
+    stream_witness, WitnessError, CKBFS_V3_HEAD_RECOVER_CHECKSUM_OFFSET, CKBFS_VERSION_V3,
+    WITNESS_WINDOW_SIZE,
+};
+
+pub use args::{Args, NumArg};
+pub use error::HasherError;
+
+// "CKBFS" + version, followed by content
+const CKBFS_V2_WITNESS_OFFSET: usize = 6;
+
+/// A checksum algorithm run by the CKBFS hashers. Argument parsing, the modes and the witness
+/// walking are shared, a hasher contract only supplies its hash.
+pub trait Hasher: Sized {
+    // prefix of debug logs, e.g. "CKB-Adler32"
+    const NAME: &'static str;
+
+    fn new() -> Self;
+    fn update(&mut self, data: &[u8]);
+    fn checksum(&self) -> u32;
+
+    // full digest of the content, which the checksum is taken from, None if the checksum is all
+    // the hasher has
+    fn digest(&self) -> Option<[u8; 32]> {
+        None
+    }
+
+    // continues from the checksum of previous content, None if a checksum is not enough to go on
+    fn recover(_checksum: u32) -> Option<Self> {
+        None
+    }
+
+    // mode 3: continues from the content before the head witness at `head_index`,
+    // by recovering from the previous checksum unless the hasher carries its own state
+    fn resume(_head_index: usize, previous_checksum: u32) -> Result<Self, HasherError> {
+        Self::recover(previous_checksum).ok_or(HasherError::Unrecoverable)
+    }
+
+    // mode 4: checksum of A || B from the checksums of A and B, and the length of B,
+    // None if the hasher can not combine
+    fn combine(_checksum_a: u32, _checksum_b: u32, _length_b: u64) -> Option<u32> {
+        None
+    }
+}
+
+fn start<H: Hasher>(recover_checksum: Option<u32>) -> Result<H, HasherError> {
+    match recover_checksum {
+        None => Ok(H::new()),
+        Some(recover_checksum) => H::recover(recover_checksum).ok_or_else(|| {
+            ckb_std::debug!(
+                "{}: can not recover from checksum 0x{}",
+                H::NAME,
+                encode_hex(&recover_checksum.to_le_bytes()).to_string_lossy()
+            );
+            HasherError::Unrecoverable
+        }),
+    }
+}
+
+/// What a hasher hands back to the contract which spawned it: the computed checksum, and the full
+/// digests of the content and of the previous content it resumed from, if the hasher has them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Report {
+    pub checksum: u32,
+    pub digest: Option<[u8; 32]>,
+    pub previous_digest: Option<[u8; 32]>,
+}
+
+impl Report {
+    fn of<H: Hasher>(hasher: &H) -> Self {
+        Report {
+            checksum: hasher.checksum(),
+            digest: hasher.digest(),
+            previous_digest: None,
+        }
+    }
+
+    // <CHECKSUM, 4 bytes LE> [<DIGEST, 32 bytes> [<PREVIOUS_DIGEST, 32 bytes>]]
+    fn encode(&self) -> Vec<u8> {
+        let mut reply = self.checksum.to_le_bytes().to_vec();
+        if let Some(digest) = self.digest {
+            reply.extend_from_slice(&digest);
+            if let Some(previous_digest) = self.previous_digest {
+                reply.extend_from_slice(&previous_digest);
+            }
+        }
+        reply
+    }
+}
+
+// hand the report back to the contract which spawned this hasher, through the pipe it passed as
+// the first inherited fd, it is a no-op when the hasher is not running as a spawned child
+fn send_report(report: &Report) -> Result<(), HasherError> {
+    let Some(&fd) = inherited_fds().first() else {
+        return Ok(());
+    };
+    let reply = report.encode();
+    let mut written = 0;
+    while written < reply.len() {
+        written += syscalls::write(fd, &reply[written..])?;
+    }
+    Ok(())
+}
+
+fn compare_checksum<H: Hasher>(expected_checksum: u32, result: Report) -> Result<(), HasherError> {
+    send_report(&result)?;
+    if result.checksum != expected_checksum {
+        ckb_std::debug!(
+            "{}: ValidateFailure, 0x{} != 0x{}",
+            H::NAME,
+            encode_hex(&result.checksum.to_le_bytes()).to_string_lossy(),
+            encode_hex(&expected_checksum.to_le_bytes()).to_string_lossy()
+        );
+        return Err(HasherError::ValidateFailure);
+    }
+    Ok(())
+}
+
+// mode 0, args should be: [CONTENT, EXPECT_CHECKSUM, RECOVER_CHECKSUM(OPTIONAL)]
+fn process_plain_validate<H: Hasher>(args: &Args) -> Result<(), HasherError> {
+    args.require(3)?;
+    let data = args.bytes(1)?;
+    let expected_checksum = args.num(2)?;
+    let mut hasher = start::<H>(args.optional_num(3)?)?;
+    hasher.update(data);
+    compare_checksum::<H>(expected_checksum, Report::of(&hasher))
+}
+
+// Mode 1 without args, streams the content of the v2 witnesses at `indexes` one after another into
+// the hasher, resuming from the content before them if a recover checksum is given
+pub fn hash_witnesses<H: Hasher>(
+    indexes: impl IntoIterator<Item = usize>,
+    recover_checksum: Option<u32>,
+) -> Result<Report, HasherError> {
+    // parts are streamed one after another into a single hasher, which is the same as
+    // recovering from the checksum of previous parts
+    let mut hasher = start::<H>(recover_checksum)?;
+    let mut window = alloc::vec![0u8; WITNESS_WINDOW_SIZE];
+    for witnesses_index in indexes {
+        load_witness_len(witnesses_index, Source::Output)
+            .and_then(|len| {
+                if len < CKBFS_V2_WITNESS_OFFSET {
+                    return Err(WitnessError::LengthNotEnough);
+                }
+                stream_witness(
+                    &mut window,
+                    witnesses_index,
+                    CKBFS_V2_WITNESS_OFFSET,
+                    len - CKBFS_V2_WITNESS_OFFSET,
+                    Source::Output,
+                    &mut |part| hasher.update(part),
+                )
+            })
+            .map_err(|err| {
+                ckb_std::debug!("{}: Failed to load witness {}: {:?}", H::NAME, witnesses_index, err);
+                HasherError::from(err)
+            })?;
+    }
+    Ok(Report::of(&hasher))
+}
+
+// mode 1, args should be: [WITNESSES_INDEX, EXPECT_CHECKSUM, RECOVER_CHECKSUM(OPTIONAL)]
+fn process_ckbfs_validate<H: Hasher>(args: &Args) -> Result<(), HasherError> {
+    args.require(3)?;
+    let witnesses_indexes = Uint32Vec::from_compatible_slice(&args.hex(1)?)
+        .map_err(|_| HasherError::InvalidWitnessIndexes)?;
+    let expected_checksum = args.num(2)?;
+    let indexes = witnesses_indexes.into_iter().map(|index| {
+        let index: u32 = index.unpack();
+        index as usize
+    });
+    let result = hash_witnesses::<H>(indexes, args.optional_num(3)?)?;
+    compare_checksum::<H>(expected_checksum, result)
+}
+
+// mode 2, args should be: [SOURCE, INDEX, OFFSET, EXPECTED_CHECKSUM, RECOVER_CHECKSUM(OPTIONAL)]
+fn process_manual_validate<H: Hasher>(args: &Args) -> Result<(), HasherError> {
+    args.require(5)?;
+    let source = args.source(1)?;
+    let index: u8 = args.num(2)?;
+    let offset: u32 = args.num(3)?;
+    let expected_checksum = args.num(4)?;
+    let data = load_cell_data(index as usize, source).map_err(|err| {
+        ckb_std::debug!("{}: Failed to load Data from {:?}[{}]", H::NAME, source, index);
+        HasherError::from(err)
+    })?;
+    let content = data.get(offset as usize..).ok_or(HasherError::InvalidOffset)?;
+
+    let mut hasher = start::<H>(args.optional_num(5)?)?;
+    hasher.update(content);
+    compare_checksum::<H>(expected_checksum, Report::of(&hasher))
+}
+
+// Mode 3 without args, streams the witness chain from `head_index` into the hasher, resuming from
+// the content before it if a recover checksum is given, and returns the final report
+pub fn hash_chain<H: Hasher>(head_index: usize, recover_checksum: Option<u32>) -> Result<Report, HasherError> {
+    // Load the fixed part of head witness first, content is streamed afterwards
+    let head_witness = load_head_witness_header(head_index, Source::Output).map_err(|err| {
+        ckb_std::debug!("{}: Failed to load head witness {}: {:?}", H::NAME, head_index, err);
+        HasherError::from(err)
+    })?;
+    if head_witness[5] != CKBFS_VERSION_V3 {
+        ckb_std::debug!("{}: Head witness version {} is not v3", H::NAME, head_witness[5]);
+        return Err(HasherError::InvalidWitnessVersion);
+    }
+    let offset = CKBFS_V3_HEAD_RECOVER_CHECKSUM_OFFSET;
+    let head_recover_checksum = u32::from_le_bytes(head_witness[offset..offset + 4].try_into().unwrap());
+
+    // A publish is told apart by the caller passing no recover checksum, not by a previous checksum
+    // of 0, which is a valid checksum of some hashers, e.g. CRC-32C of empty content
+    let mut hasher = match recover_checksum {
+        None => H::new(),
+        // The caller knows the checksum it is appending to, the head witness must agree with it
+        Some(recover_checksum) if recover_checksum != head_recover_checksum => {
+            ckb_std::debug!(
+                "{}: Head witness previous checksum does not match recover checksum {}",
+                H::NAME,
+                recover_checksum
+            );
+            return Err(HasherError::ValidateFailure);
+        }
+        Some(recover_checksum) => H::resume(head_index, recover_checksum).inspect_err(|_| {
+            ckb_std::debug!("{}: Failed to resume from head witness {}", H::NAME, head_index);
+        })?,
+    };
+    // the contract binds the resumed state to the digest it kept for the previous content
+    let previous_digest = recover_checksum.and(hasher.digest());
+
+    // Stream all witness content parts into the hasher,
+    // the chain must move forward and stay inside the transaction
+    stream_chain_content(head_index, Source::Output, |part| hasher.update(part)).map_err(|err| {
+        ckb_std::debug!("{}: Failed to load witness chain from {}: {:?}", H::NAME, head_index, err);
+        HasherError::from(err)
+    })?;
+    Ok(Report {
+        previous_digest,
+        ..Report::of(&hasher)
+    })
+}
+
+// mode 3, args should be: [FIRST_WITNESS_INDEX, EXPECT_CHECKSUM, RECOVER_CHECKSUM(OPTIONAL)]
+fn process_ckbfs_validate_v3<H: Hasher>(args: &Args) -> Result<(), HasherError> {
+    args.require(3)?;
+    let head_index: u32 = args.num(1)?;
+    let expected_checksum = args.num(2)?;
+    let result = hash_chain::<H>(head_index as usize, args.optional_num(3)?)?;
+    compare_checksum::<H>(expected_checksum, result)
+}
+
+// mode 4, args should be: [CHECKSUM_A, CHECKSUM_B, LENGTH_B, EXPECT_CHECKSUM]
+fn process_combine_validate<H: Hasher>(args: &Args) -> Result<(), HasherError> {
+    args.require(5)?;
+    let checksum_a = args.num(1)?;
+    let checksum_b = args.num(2)?;
+    let length_b = args.num(3)?;
+    let expected_checksum = args.num(4)?;
+
+    // no content is hashed, the checksum of the concatenation follows from the parts
+    let checksum = H::combine(checksum_a, checksum_b, length_b).ok_or_else(|| {
+        ckb_std::debug!("{}: UnknownMode, can not combine checksums", H::NAME);
+        HasherError::UnknownMode
+    })?;
+    let result = Report {
+        checksum,
+        digest: None,
+        previous_digest: None,
+    };
+    compare_checksum::<H>(expected_checksum, result)
+}
+
+// Loads the hasher state of the previous content from `input_type` of a wrapped head witness,
+// whose CKBFS payload must be in `output_type`, so the state is never taken as content
+pub fn load_head_witness_state(head_index: usize) -> Result<Vec<u8>, HasherError> {
+    if !is_wrapped_head_witness(head_index, Source::Output) {
+        return Err(HasherError::InvalidState);
+    }
+    let witness_args = load_witness_args(head_index, Source::Output)?;
+    if witness_args.output_type().to_opt().is_none() {
+        return Err(HasherError::InvalidState);
+    }
+    let state = witness_args.input_type().to_opt().ok_or(HasherError::InvalidState)?;
+    Ok(state.raw_data().to_vec())
+}
+
+/// Runs the hasher with the args of the program, returns the exit code
+pub fn run<H: Hasher>() -> i8 {
+    let args = ckb_std::env::argv();
+
+    let result = match args.len() {
+        // do nothing if no args provided
+        0 => Ok(()),
+        // only do hash if one arg exist
+        1 => {
+            let mut hasher = H::new();
+            hasher.update(args[0].to_bytes());
+            ckb_std::debug!("0x{}", encode_hex(&hasher.checksum().to_le_bytes()).to_string_lossy());
+            Ok(())
+        }
+        // arg rules should be like: [<MODE>, ...<OTHER ARGS>]
+        _ => {
+            let args = Args(args);
+            args.num::<u8>(0).and_then(|mode| match mode {
+                // 0 - plain validate mode
+                0 => process_plain_validate::<H>(&args),
+                // 1 - (for contract) ckbfs v2 validate mode
+                1 => process_ckbfs_validate::<H>(&args),
+                // 2 - (for contract) manual validate mode; load data from where you want
+                2 => process_manual_validate::<H>(&args),
+                // 3 - (for contract) ckbfs v3 validate mode
+                3 => process_ckbfs_validate_v3::<H>(&args),
+                // 4 - (for contract) combine validate mode; validates checksum of A || B without content
+                4 => process_combine_validate::<H>(&args),
+                _ => {
+                    ckb_std::debug!("{}: UnknownMode {}", H::NAME, mode);
+                    Err(HasherError::UnknownMode)
+                }
+            })
+        }
+    };
+
+    // every failure exits with its own code, so the spawning contract can tell them apart
+    match result {
+        Ok(()) => 0,
+        Err(err) => err as i8,
+    }
+}
@@ -0,0 +1,208 @@
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
+
+// A manifest is a CKBFS v3 file with this content type, its content is a sequence of ManifestEntry:
+// table ManifestEntry { path: Bytes, type_id: Byte32, checksum: Uint32 }
+// Later entries override earlier ones with the same path, so appending works like it does for files.
+pub const MANIFEST_CONTENT_TYPE: &[u8] = b"application/vnd.ckbfs.manifest";
+
+// nested manifests are followed at most this deep while resolving
+pub const MAX_RESOLVE_DEPTH: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    Encoding,
+    InvalidPath,
+    NotFound,
+    TooDeep,
+}
+
+// a CKBFS file, pinned to its checksum when it was added to the manifest
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Target {
+    pub type_id: [u8; 32],
+    pub checksum: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry<'a> {
+    pub path: &'a str,
+    pub target: Target,
+}
+
+impl ManifestEntry<'_> {
+    // an all zero type id removes the path
+    pub fn is_removal(&self) -> bool {
+        self.target.type_id == [0u8; 32]
+    }
+
+    // a path ending with "/" points to another manifest
+    pub fn is_directory(&self) -> bool {
+        self.path.ends_with('/')
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let path_len = self.path.len();
+        let header_size = 4 + 3 * 4;
+        let total_size = header_size + 4 + path_len + 32 + 4;
+        let mut result = Vec::with_capacity(total_size);
+        for value in [
+            total_size,
+            header_size,
+            header_size + 4 + path_len,
+            header_size + 4 + path_len + 32,
+            path_len,
+        ] {
+            result.extend_from_slice(&(value as u32).to_le_bytes());
+        }
+        result.extend_from_slice(self.path.as_bytes());
+        result.extend_from_slice(&self.target.type_id);
+        result.extend_from_slice(&self.target.checksum.to_le_bytes());
+        result
+    }
+}
+
+fn read_u32(slice: &[u8], offset: usize) -> Result<usize, Error> {
+    slice
+        .get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()) as usize)
+        .ok_or(Error::Encoding)
+}
+
+// paths are relative and "/" separated, without empty, "." or ".." segments,
+// a trailing "/" marks a directory
+pub fn validate_path(path: &str) -> Result<(), Error> {
+    let path = path.strip_suffix('/').unwrap_or(path);
+    if path
+        .split('/')
+        .any(|segment| segment.is_empty() || segment == "." || segment == "..")
+    {
+        return Err(Error::InvalidPath);
+    }
+    Ok(())
+}
+
+fn parse_entry(slice: &[u8]) -> Result<ManifestEntry<'_>, Error> {
+    // exactly 3 fields, no extension
+    if read_u32(slice, 4)? != 16 {
+        return Err(Error::Encoding);
+    }
+    let offsets = [read_u32(slice, 4)?, read_u32(slice, 8)?, read_u32(slice, 12)?, slice.len()];
+    if offsets.windows(2).any(|pair| pair[0] > pair[1]) {
+        return Err(Error::Encoding);
+    }
+    let (path, type_id, checksum) = (
+        &slice[offsets[0]..offsets[1]],
+        &slice[offsets[1]..offsets[2]],
+        &slice[offsets[2]..offsets[3]],
+    );
+
+    if read_u32(path, 0)? != path.len() - 4 {
+        return Err(Error::Encoding);
+    }
+    let path = core::str::from_utf8(&path[4..]).map_err(|_| Error::InvalidPath)?;
+    validate_path(path)?;
+
+    let entry = ManifestEntry {
+        path,
+        target: Target {
+            type_id: type_id.try_into().map_err(|_| Error::Encoding)?,
+            checksum: u32::from_le_bytes(checksum.try_into().map_err(|_| Error::Encoding)?),
+        },
+    };
+    // removals carry no checksum, so a manifest has a single encoding
+    if entry.is_removal() && entry.target.checksum != 0 {
+        return Err(Error::Encoding);
+    }
+    Ok(entry)
+}
+
+// parses manifest content, or a part of it appended later
+pub fn parse_entries(content: &[u8]) -> Result<Vec<ManifestEntry<'_>>, Error> {
+    let mut entries = Vec::new();
+    let mut offset = 0;
+    while offset < content.len() {
+        let total_size = read_u32(content, offset)?;
+        let entry = content
+            .get(offset..offset + total_size)
+            .ok_or(Error::Encoding)?;
+        entries.push(parse_entry(entry)?);
+        offset += total_size;
+    }
+    Ok(entries)
+}
+
+// the latest state of a manifest
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Manifest {
+    entries: BTreeMap<String, Target>,
+}
+
+impl Manifest {
+    pub fn from_content(content: &[u8]) -> Result<Self, Error> {
+        let mut manifest = Self::default();
+        manifest.apply(content)?;
+        Ok(manifest)
+    }
+
+    // applies appended content
+    pub fn apply(&mut self, content: &[u8]) -> Result<(), Error> {
+        for entry in parse_entries(content)? {
+            if entry.is_removal() {
+                self.entries.remove(entry.path);
+            } else {
+                self.entries.insert(String::from(entry.path), entry.target);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, path: &str) -> Option<&Target> {
+        self.entries.get(path)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Target)> {
+        self.entries.iter().map(|(path, target)| (path.as_str(), target))
+    }
+
+    // the deepest directory containing the path, and the rest of the path inside it
+    fn directory_of<'p>(&self, path: &'p str) -> Option<(&Target, &'p str)> {
+        path.match_indices('/')
+            .rev()
+            .find_map(|(index, _)| {
+                self.entries
+                    .get(&path[..=index])
+                    .map(|target| (target, &path[index + 1..]))
+            })
+    }
+}
+
+// Resolves a path to a file, following directories into nested manifests.
+// `load_manifest` returns the content of a nested manifest, it is up to the caller whether to load
+// the pinned version, or the live cell when its checksum still matches.
+pub fn resolve<F>(root: &Manifest, path: &str, mut load_manifest: F) -> Result<Target, Error>
+where
+    F: FnMut(&Target) -> Result<Vec<u8>, Error>,
+{
+    validate_path(path)?;
+
+    let mut nested;
+    let mut manifest = root;
+    let mut path = path;
+    for _ in 0..MAX_RESOLVE_DEPTH {
+        if let Some(target) = manifest.get(path) {
+            return Ok(*target);
+        }
+        let (directory, rest) = manifest.directory_of(path).ok_or(Error::NotFound)?;
+        if rest.is_empty() {
+            return Err(Error::NotFound);
+        }
+        nested = Manifest::from_content(&load_manifest(directory)?)?;
+        manifest = &nested;
+        path = rest;
+    }
+    Err(Error::TooDeep)
+}
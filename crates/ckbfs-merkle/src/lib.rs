@@ -0,0 +1,344 @@
+#![no_std]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use ckbfs_blake2b::Blake2b;
+
+pub const CHUNK_SIZE: usize = 4096;
+pub const HASH_SIZE: usize = 32;
+
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+const ROOT_PREFIX: u8 = 0x02;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    // serialized state is malformed
+    InvalidState,
+    // range is empty, or not inside the file
+    InvalidRange,
+    // proof is malformed, or does not lead to the root
+    InvalidProof,
+}
+
+pub type Hash = [u8; HASH_SIZE];
+
+fn hash(prefix: u8, parts: &[&[u8]]) -> Hash {
+    let mut hasher = Blake2b::new();
+    hasher.update(&[prefix]);
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize()
+}
+
+pub fn leaf_hash(chunk: &[u8]) -> Hash {
+    hash(LEAF_PREFIX, &[chunk])
+}
+
+pub fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    hash(NODE_PREFIX, &[left, right])
+}
+
+pub fn root_hash(length: u64, peaks: &[Hash]) -> Hash {
+    let mut hasher = Blake2b::new();
+    hasher.update(&[ROOT_PREFIX]);
+    hasher.update(&length.to_le_bytes());
+    for peak in peaks {
+        hasher.update(peak);
+    }
+    hasher.finalize()
+}
+
+pub fn checksum(root: &Hash) -> u32 {
+    u32::from_le_bytes(root[..4].try_into().unwrap())
+}
+
+// Sizes of the perfect trees over `length` bytes of content, from left to right. Full chunks
+// make trees of decreasing powers of two like a Merkle mountain range, a partial last chunk is
+// a tree of its own.
+fn tree_sizes(length: u64) -> impl Iterator<Item = u64> {
+    let full_chunks = length / CHUNK_SIZE as u64;
+    let partial = !length.is_multiple_of(CHUNK_SIZE as u64);
+    (0..u64::BITS)
+        .rev()
+        .map(|bit| 1u64 << bit)
+        .filter(move |size| full_chunks & size != 0)
+        .chain(partial.then_some(1))
+}
+
+// Merkle mountain range accumulator, content can be pushed in any pieces
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChunkTree {
+    length: u64,
+    // one peak for each set bit of the full chunk count, kept by `update` and checked by
+    // `deserialize`, so no state can be built which `push_leaf` has to reject
+    peaks: Vec<Hash>,
+    tail: Vec<u8>,
+}
+
+impl ChunkTree {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> u64 {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            let take = data.len().min(CHUNK_SIZE - self.tail.len());
+            self.tail.extend_from_slice(&data[..take]);
+            self.length += take as u64;
+            data = &data[take..];
+
+            if self.tail.len() == CHUNK_SIZE {
+                let leaf = leaf_hash(&self.tail);
+                self.tail.clear();
+                self.push_leaf(leaf);
+            }
+        }
+    }
+
+    // merges equal sized trees, same as carrying in a binary increment of the full chunk count
+    fn push_leaf(&mut self, leaf: Hash) {
+        let mut node = leaf;
+        let mut full_chunks = self.length / CHUNK_SIZE as u64 - 1;
+        while full_chunks & 1 == 1 {
+            // internal invariant, see `peaks`
+            let left = self.peaks.pop().expect("a peak for each set bit");
+            node = node_hash(&left, &node);
+            full_chunks >>= 1;
+        }
+        self.peaks.push(node);
+    }
+
+    pub fn root(&self) -> Hash {
+        if self.tail.is_empty() {
+            return root_hash(self.length, &self.peaks);
+        }
+        let mut peaks = self.peaks.clone();
+        peaks.push(leaf_hash(&self.tail));
+        root_hash(self.length, &peaks)
+    }
+
+    pub fn checksum(&self) -> u32 {
+        checksum(&self.root())
+    }
+
+    // length(8, u64 little endian) + peaks(32 * number of full chunk trees) + partial last chunk
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut state = Vec::with_capacity(8 + self.peaks.len() * HASH_SIZE + self.tail.len());
+        state.extend_from_slice(&self.length.to_le_bytes());
+        for peak in self.peaks.iter() {
+            state.extend_from_slice(peak);
+        }
+        state.extend_from_slice(&self.tail);
+        state
+    }
+
+    pub fn deserialize(state: &[u8]) -> Result<Self, Error> {
+        let length = u64::from_le_bytes(state.get(..8).ok_or(Error::InvalidState)?.try_into().unwrap());
+        let peak_count = (length / CHUNK_SIZE as u64).count_ones() as usize;
+        let tail_len = (length % CHUNK_SIZE as u64) as usize;
+        if state.len() != 8 + peak_count * HASH_SIZE + tail_len {
+            return Err(Error::InvalidState);
+        }
+
+        let peaks_end = 8 + peak_count * HASH_SIZE;
+        Ok(Self {
+            length,
+            peaks: state[8..peaks_end]
+                .chunks_exact(HASH_SIZE)
+                .map(|peak| peak.try_into().unwrap())
+                .collect(),
+            tail: state[peaks_end..].to_vec(),
+        })
+    }
+}
+
+pub fn root(content: &[u8]) -> Hash {
+    let mut tree = ChunkTree::new();
+    tree.update(content);
+    tree.root()
+}
+
+fn subtree_root(leaves: &[Hash]) -> Hash {
+    if leaves.len() == 1 {
+        return leaves[0];
+    }
+    let (left, right) = leaves.split_at(leaves.len() / 2);
+    node_hash(&subtree_root(left), &subtree_root(right))
+}
+
+// Proof that some bytes are at a position of a file. The chunks covering the range are rebuilt
+// from the bytes with `prefix` and `suffix`, every other part of the chunk tree is given by
+// `hashes`: roots of the largest subtrees outside of the covered chunks, from left to right.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RangeProof {
+    pub length: u64,
+    pub prefix: Vec<u8>,
+    pub suffix: Vec<u8>,
+    pub hashes: Vec<Hash>,
+}
+
+// chunks covering bytes [start, end), as [first, last) chunk indexes
+fn covered_chunks(start: u64, end: u64) -> (u64, u64) {
+    (start / CHUNK_SIZE as u64, (end - 1) / CHUNK_SIZE as u64 + 1)
+}
+
+fn collect_hashes(leaves: &[Hash], offset: u64, covered: (u64, u64), hashes: &mut Vec<Hash>) {
+    let end = offset + leaves.len() as u64;
+    if covered.0 <= offset && end <= covered.1 {
+        return;
+    }
+    if end <= covered.0 || covered.1 <= offset {
+        hashes.push(subtree_root(leaves));
+        return;
+    }
+    let (left, right) = leaves.split_at(leaves.len() / 2);
+    collect_hashes(left, offset, covered, hashes);
+    collect_hashes(right, offset + left.len() as u64, covered, hashes);
+}
+
+// Builds a proof of bytes [start, end) of `content`
+pub fn prove_range(content: &[u8], start: u64, end: u64) -> Result<RangeProof, Error> {
+    let length = content.len() as u64;
+    if start >= end || end > length {
+        return Err(Error::InvalidRange);
+    }
+
+    let leaves: Vec<Hash> = content.chunks(CHUNK_SIZE).map(leaf_hash).collect();
+    let covered = covered_chunks(start, end);
+    let mut hashes = Vec::new();
+    let mut offset = 0;
+    for size in tree_sizes(length) {
+        collect_hashes(&leaves[offset as usize..(offset + size) as usize], offset, covered, &mut hashes);
+        offset += size;
+    }
+
+    let covered_end = (covered.1 * CHUNK_SIZE as u64).min(length);
+    Ok(RangeProof {
+        length,
+        prefix: content[(covered.0 * CHUNK_SIZE as u64) as usize..start as usize].to_vec(),
+        suffix: content[end as usize..covered_end as usize].to_vec(),
+        hashes,
+    })
+}
+
+struct Verifier<'a, I: Iterator<Item = &'a Hash>> {
+    covered: (u64, u64),
+    leaves: Vec<Hash>,
+    hashes: I,
+}
+
+impl<'a, I: Iterator<Item = &'a Hash>> Verifier<'a, I> {
+    fn subtree_root(&mut self, offset: u64, size: u64) -> Result<Hash, Error> {
+        let end = offset + size;
+        if self.covered.0 <= offset && end <= self.covered.1 {
+            let first = (offset - self.covered.0) as usize;
+            return Ok(subtree_root(&self.leaves[first..first + size as usize]));
+        }
+        if end <= self.covered.0 || self.covered.1 <= offset {
+            return self.hashes.next().copied().ok_or(Error::InvalidProof);
+        }
+        let left = self.subtree_root(offset, size / 2)?;
+        let right = self.subtree_root(offset + size / 2, size / 2)?;
+        Ok(node_hash(&left, &right))
+    }
+}
+
+impl RangeProof {
+    // Computes the root of the file from `data`, which are the proven bytes starting at `start`
+    pub fn compute_root(&self, start: u64, data: &[u8]) -> Result<Hash, Error> {
+        // `start` comes with the proof, the range must not wrap around
+        let end = start.checked_add(data.len() as u64).ok_or(Error::InvalidProof)?;
+        if data.is_empty() || end > self.length {
+            return Err(Error::InvalidRange);
+        }
+        let covered = covered_chunks(start, end);
+        let covered_end = covered.1.saturating_mul(CHUNK_SIZE as u64).min(self.length);
+        if self.prefix.len() as u64 != start - covered.0 * CHUNK_SIZE as u64
+            || self.suffix.len() as u64 != covered_end - end
+        {
+            return Err(Error::InvalidProof);
+        }
+
+        let mut chunks = Vec::with_capacity(self.prefix.len() + data.len() + self.suffix.len());
+        chunks.extend_from_slice(&self.prefix);
+        chunks.extend_from_slice(data);
+        chunks.extend_from_slice(&self.suffix);
+        let mut verifier = Verifier {
+            covered,
+            leaves: chunks.chunks(CHUNK_SIZE).map(leaf_hash).collect(),
+            hashes: self.hashes.iter(),
+        };
+
+        let mut peaks = Vec::new();
+        let mut offset = 0;
+        for size in tree_sizes(self.length) {
+            peaks.push(verifier.subtree_root(offset, size)?);
+            offset += size;
+        }
+        if verifier.hashes.next().is_some() {
+            return Err(Error::InvalidProof);
+        }
+        Ok(root_hash(self.length, &peaks))
+    }
+
+    pub fn verify(&self, root: &Hash, start: u64, data: &[u8]) -> Result<(), Error> {
+        if self.compute_root(start, data)? != *root {
+            return Err(Error::InvalidProof);
+        }
+        Ok(())
+    }
+
+    // length(8) + prefix length(4) + prefix + suffix length(4) + suffix + hashes, integers are little endian
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&self.length.to_le_bytes());
+        bytes.extend_from_slice(&(self.prefix.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&self.prefix);
+        bytes.extend_from_slice(&(self.suffix.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&self.suffix);
+        for hash in self.hashes.iter() {
+            bytes.extend_from_slice(hash);
+        }
+        bytes
+    }
+
+    pub fn decode(mut bytes: &[u8]) -> Result<Self, Error> {
+        let mut take = |len: usize| -> Result<&[u8], Error> {
+            if bytes.len() < len {
+                return Err(Error::InvalidProof);
+            }
+            let (taken, rest) = bytes.split_at(len);
+            bytes = rest;
+            Ok(taken)
+        };
+        let length = u64::from_le_bytes(take(8)?.try_into().unwrap());
+        let prefix_len = u32::from_le_bytes(take(4)?.try_into().unwrap()) as usize;
+        let prefix = take(prefix_len)?.to_vec();
+        let suffix_len = u32::from_le_bytes(take(4)?.try_into().unwrap()) as usize;
+        let suffix = take(suffix_len)?.to_vec();
+        if !bytes.len().is_multiple_of(HASH_SIZE) {
+            return Err(Error::InvalidProof);
+        }
+        let hashes = bytes
+            .chunks_exact(HASH_SIZE)
+            .map(|hash| hash.try_into().unwrap())
+            .collect();
+        Ok(Self {
+            length,
+            prefix,
+            suffix,
+            hashes,
+        })
+    }
+}
@@ -0,0 +1,244 @@
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{vec, vec::Vec};
+use ckb_std::{ckb_constants::Source, error::SysError, syscalls};
+
+pub const CKBFS_HEADER: [u8; 5] = *b"CKBFS";
+pub const CKBFS_VERSION_V3: u8 = 0x03;
+
+// Head witness structure: CKBFS(5) + version(1) + prev_position(36) + prev_checksum(4) + next_index(4) + content
+// Middle/Tail witness structure: next_index(4) + content
+pub const CKBFS_V3_HEAD_RECOVER_CHECKSUM_OFFSET: usize = 42; // 5 + 1 + 36
+pub const CKBFS_V3_HEAD_NEXT_INDEX_OFFSET: usize = 46; // 5 + 1 + 36 + 4
+pub const CKBFS_V3_HEAD_WITNESS_OFFSET: usize = 50; // 5 + 1 + 36 + 4 + 4
+pub const CKBFS_V3_MIDDLE_WITNESS_OFFSET: usize = 4;
+
+// upper bound of witnesses in a single v3 chain, head witness included
+pub const MAX_WITNESS_CHAIN_LENGTH: usize = 1024;
+// witness content is streamed in windows of this size, so memory usage does not grow with the file
+pub const WITNESS_WINDOW_SIZE: usize = 16 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WitnessError {
+    Sys(SysError),
+    // payload is shorter than the witness header
+    LengthNotEnough,
+    // witness is not a valid WitnessArgs, or carries no payload
+    InvalidWitnessArgs,
+    IndexRepeated,
+    IndexBackward,
+    IndexOutOfBound,
+    ChainTooLong,
+}
+
+impl From<SysError> for WitnessError {
+    fn from(err: SysError) -> Self {
+        Self::Sys(err)
+    }
+}
+
+// loads witness bytes from `offset`, the witness must be long enough to fill `buf`
+pub fn load_witness_exact(
+    buf: &mut [u8],
+    offset: usize,
+    index: usize,
+    source: Source,
+) -> Result<(), WitnessError> {
+    match syscalls::load_witness(buf, offset, index, source) {
+        Ok(len) if len == buf.len() => Ok(()),
+        Ok(_) => Err(WitnessError::LengthNotEnough),
+        Err(SysError::LengthNotEnough(_)) => Ok(()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+pub fn load_witness_len(index: usize, source: Source) -> Result<usize, WitnessError> {
+    match syscalls::load_witness(&mut [], 0, index, source) {
+        Ok(len) | Err(SysError::LengthNotEnough(len)) => Ok(len),
+        Err(err) => Err(err.into()),
+    }
+}
+
+// a raw head witness starts with "CKBFS", otherwise it is wrapped in a WitnessArgs
+pub fn is_wrapped_head_witness(index: usize, source: Source) -> bool {
+    let mut magic = [0u8; 5];
+    load_witness_exact(&mut magic, 0, index, source).is_err() || magic != CKBFS_HEADER
+}
+
+// Locates the CKBFS payload of a witness, returns its offset and length. A raw witness is the
+// payload itself, a wrapped one is a WitnessArgs carrying the payload in `output_type`, or in
+// `input_type` when there's no `output_type` (e.g. a transfer, which has no content for outputs)
+pub fn locate_witness_payload(
+    index: usize,
+    source: Source,
+    wrapped: bool,
+) -> Result<(usize, usize), WitnessError> {
+    let witness_len = load_witness_len(index, source)?;
+    if !wrapped {
+        return Ok((0, witness_len));
+    }
+
+    // WitnessArgs: table { lock: BytesOpt, input_type: BytesOpt, output_type: BytesOpt }
+    let mut header = [0u8; 16];
+    load_witness_exact(&mut header, 0, index, source).map_err(|_| WitnessError::InvalidWitnessArgs)?;
+    let field = |i: usize| u32::from_le_bytes(header[i * 4..i * 4 + 4].try_into().unwrap()) as usize;
+    let (total_size, lock_start, input_type_start, output_type_start) =
+        (field(0), field(1), field(2), field(3));
+    if total_size != witness_len
+        || lock_start != header.len()
+        || input_type_start < lock_start
+        || output_type_start < input_type_start
+        || total_size < output_type_start
+    {
+        return Err(WitnessError::InvalidWitnessArgs);
+    }
+
+    let (start, end) = if output_type_start < total_size {
+        (output_type_start, total_size)
+    } else if input_type_start < output_type_start {
+        (input_type_start, output_type_start)
+    } else {
+        return Err(WitnessError::InvalidWitnessArgs);
+    };
+
+    // Bytes: item count + items
+    let mut count = [0u8; 4];
+    if end - start < count.len() {
+        return Err(WitnessError::InvalidWitnessArgs);
+    }
+    load_witness_exact(&mut count, start, index, source)?;
+    let len = u32::from_le_bytes(count) as usize;
+    if len != end - start - count.len() {
+        return Err(WitnessError::InvalidWitnessArgs);
+    }
+    Ok((start + count.len(), len))
+}
+
+// Locates the CKBFS payload of a head witness, returns its offset and length, see locate_witness_payload
+pub fn locate_head_witness_payload(index: usize, source: Source) -> Result<(usize, usize), WitnessError> {
+    locate_witness_payload(index, source, is_wrapped_head_witness(index, source))
+}
+
+// loads the fixed part of a head witness, content is left to the caller and can be larger than the heap
+pub fn load_head_witness_header(
+    index: usize,
+    source: Source,
+) -> Result<[u8; CKBFS_V3_HEAD_WITNESS_OFFSET], WitnessError> {
+    let (offset, len) = locate_head_witness_payload(index, source)?;
+    if len < CKBFS_V3_HEAD_WITNESS_OFFSET {
+        return Err(WitnessError::LengthNotEnough);
+    }
+    let mut header = [0u8; CKBFS_V3_HEAD_WITNESS_OFFSET];
+    load_witness_exact(&mut header, offset, index, source)?;
+    Ok(header)
+}
+
+pub fn load_head_witness_payload(index: usize, source: Source) -> Result<Vec<u8>, WitnessError> {
+    let (offset, len) = locate_head_witness_payload(index, source)?;
+    let mut payload = vec![0u8; len];
+    load_witness_exact(&mut payload, offset, index, source)?;
+    Ok(payload)
+}
+
+fn load_next_index(offset: usize, index: usize, source: Source) -> Result<usize, WitnessError> {
+    let mut next_index = [0u8; 4];
+    load_witness_exact(&mut next_index, offset, index, source)?;
+    Ok(u32::from_le_bytes(next_index) as usize)
+}
+
+// Walks the v3 witness chain from the head witness, and calls `visit` with the index, content
+// offset and content length of every witness in order. The chain must move forward, stay inside
+// the transaction, and visit at most MAX_WITNESS_CHAIN_LENGTH witnesses, checked in this order.
+pub fn walk_chain<E: From<WitnessError>>(
+    head_index: usize,
+    source: Source,
+    mut visit: impl FnMut(usize, usize, usize) -> Result<(), E>,
+) -> Result<(), E> {
+    let wrapped = is_wrapped_head_witness(head_index, source);
+    let (offset, len) = locate_witness_payload(head_index, source, wrapped)?;
+    if len < CKBFS_V3_HEAD_WITNESS_OFFSET {
+        return Err(WitnessError::LengthNotEnough.into());
+    }
+    visit(
+        head_index,
+        offset + CKBFS_V3_HEAD_WITNESS_OFFSET,
+        len - CKBFS_V3_HEAD_WITNESS_OFFSET,
+    )?;
+
+    let mut visited = vec![head_index];
+    let mut next_index = load_next_index(offset + CKBFS_V3_HEAD_NEXT_INDEX_OFFSET, head_index, source)?;
+    while next_index != 0 {
+        if visited.contains(&next_index) {
+            return Err(WitnessError::IndexRepeated.into());
+        }
+        if next_index < *visited.last().unwrap() {
+            return Err(WitnessError::IndexBackward.into());
+        }
+        if visited.len() >= MAX_WITNESS_CHAIN_LENGTH {
+            return Err(WitnessError::ChainTooLong.into());
+        }
+
+        // the whole chain follows the head witness, either all raw or all wrapped
+        let (offset, len) = match locate_witness_payload(next_index, source, wrapped) {
+            Err(WitnessError::Sys(SysError::IndexOutOfBound)) => {
+                return Err(WitnessError::IndexOutOfBound.into())
+            }
+            result => result?,
+        };
+        if len < CKBFS_V3_MIDDLE_WITNESS_OFFSET {
+            return Err(WitnessError::LengthNotEnough.into());
+        }
+        visit(
+            next_index,
+            offset + CKBFS_V3_MIDDLE_WITNESS_OFFSET,
+            len - CKBFS_V3_MIDDLE_WITNESS_OFFSET,
+        )?;
+
+        visited.push(next_index);
+        next_index = load_next_index(offset, next_index, source)?;
+    }
+    Ok(())
+}
+
+// feeds `len` witness bytes from `offset` to `update`, one window at a time
+pub fn stream_witness(
+    window: &mut [u8],
+    index: usize,
+    mut offset: usize,
+    len: usize,
+    source: Source,
+    update: &mut impl FnMut(&[u8]),
+) -> Result<(), WitnessError> {
+    let end = offset + len;
+    while offset < end {
+        let size = core::cmp::min(window.len(), end - offset);
+        load_witness_exact(&mut window[..size], offset, index, source)?;
+        update(&window[..size]);
+        offset += size;
+    }
+    Ok(())
+}
+
+// feeds all content parts of a v3 witness chain to `update` in order
+pub fn stream_chain_content(
+    head_index: usize,
+    source: Source,
+    mut update: impl FnMut(&[u8]),
+) -> Result<(), WitnessError> {
+    let mut window = vec![0u8; WITNESS_WINDOW_SIZE];
+    walk_chain(head_index, source, |index, offset, len| {
+        stream_witness(&mut window, index, offset, len, source, &mut update)
+    })
+}
+
+// indexes of all witnesses in a v3 witness chain
+pub fn chain_witness_indexes(head_index: usize, source: Source) -> Result<Vec<usize>, WitnessError> {
+    let mut indexes = Vec::new();
+    walk_chain(head_index, source, |index, _, _| {
+        indexes.push(index);
+        Ok::<_, WitnessError>(())
+    })?;
+    Ok(indexes)
+}
@@ -0,0 +1,54 @@
+#![no_std]
+#![cfg_attr(not(test), no_main)]
+
+#[cfg(test)]
+extern crate alloc;
+
+#[cfg(not(test))]
+use ckb_std::default_alloc;
+
+#[cfg(not(test))]
+ckb_std::entry!(program_entry);
+
+#[cfg(not(test))]
+default_alloc!();
+
+use alloc::{ffi::CString, vec::Vec};
+use ckb_std::{
+    ckb_constants::Source,
+    ckb_types::core::ScriptHashType,
+    high_level::{load_script, load_witness, spawn_cell},
+    syscalls,
+};
+
+// exit code when the hasher can not be spawned, or args are malformed
+const RUNNER_FAILURE: i8 = 1;
+
+// Lock script args: <hasher data1 hash, 32 bytes>
+// Witness 0 of the group: args of the hasher, each one terminated by NUL
+pub fn program_entry() -> i8 {
+    run().unwrap_or(RUNNER_FAILURE)
+}
+
+// spawns the hasher with the args, and exits with its exit code
+fn run() -> Option<i8> {
+    let script = load_script().ok()?;
+    let code_hash = script.args().raw_data();
+    if code_hash.len() != 32 {
+        return None;
+    }
+
+    let witness = load_witness(0, Source::GroupInput).ok()?;
+    let args = match witness.strip_suffix(&[0]) {
+        None if witness.is_empty() => Vec::new(),
+        None => return None,
+        Some(args) => args
+            .split(|byte| *byte == 0)
+            .map(|arg| CString::new(arg).ok())
+            .collect::<Option<Vec<_>>>()?,
+    };
+    let argv = args.iter().map(CString::as_c_str).collect::<Vec<_>>();
+
+    let pid = spawn_cell(&code_hash, ScriptHashType::Data1, &argv, &[]).ok()?;
+    syscalls::wait(pid).ok()
+}
@@ -79,6 +79,14 @@ impl Loader {
         Loader(base_path)
     }
 
+    // test-only contracts are built apart from the deployable ones, by `make build-test-contracts`
+    pub fn test_contracts() -> Self {
+        let Loader(mut path) = Self::default();
+        let mode = path.file_name().expect("mode").to_string_lossy().into_owned();
+        path.set_file_name(format!("test-{mode}"));
+        Loader(path)
+    }
+
     pub fn load_binary(&self, name: &str) -> Bytes {
         let mut path = self.0.clone();
         path.push(name);
@@ -2,12 +2,77 @@ use crate::Loader;
 use ckb_testtool::builtin::ALWAYS_SUCCESS;
 use ckb_testtool::ckb_hash::Blake2bBuilder;
 use ckb_testtool::ckb_types::core::DepType;
-use ckb_testtool::ckb_types::{bytes::Bytes, core::TransactionBuilder, packed::*, prelude::*};
+use ckb_testtool::ckb_types::{
+    bytes::Bytes,
+    core::{TransactionBuilder, TransactionView},
+    packed::*,
+    prelude::*,
+};
 use ckb_testtool::context::Context;
-use ckbfs_types::{CKBFSData, CKBFSDataNative, };
+use ckb_testtool::ckb_error::Error;
+use ckbfs_manifest::{Manifest, ManifestEntry, Target, MANIFEST_CONTENT_TYPE};
+use ckbfs_types::{CKBFSData, CKBFSDataNative};
+use ckbfs_types_v2::{
+    BackLinkNative, CKBFSData as CKBFSDataV2, CKBFSDataNative as CKBFSDataNativeV2,
+};
+use molecule::prelude::Entity as _;
 // Include your tests here
 // See https://github.com/xxuejie/ckb-native-build-sample/blob/main/tests/src/tests.rs for more examples
 
+const MAX_CYCLES: u64 = 10_000_000;
+
+// CKBFS contract error codes, see contracts/ckbfs/src/error.rs
+const ERROR_CHECKSUM_MISMATCH: i8 = 104;
+const ERROR_INVALID_WITNESS_HEADER: i8 = 110;
+const ERROR_INVALID_VERSION: i8 = 111;
+const ERROR_INVALID_PREVIOUS_CHECKSUM: i8 = 113;
+const ERROR_INVALID_FORK_SOURCE: i8 = 115;
+const ERROR_INVALID_BACKLINKS: i8 = 116;
+const ERROR_INVALID_FIELD_UPDATE: i8 = 105;
+const ERROR_NO_CHECKSUM_HASHER_FOUND: i8 = 106;
+const ERROR_INVALID_HASHER_HASH_TYPE: i8 = 118;
+const ERROR_FILE_SEALED: i8 = 119;
+const ERROR_UNAUTHORIZED_APPEND: i8 = 120;
+const ERROR_UNAUTHORIZED_UPDATE: i8 = 121;
+const ERROR_INVALID_APPEND: i8 = 108;
+const ERROR_CONTENT_HASH_MISMATCH: i8 = 122;
+const ERROR_LENGTH_MISMATCH: i8 = 123;
+const ERROR_INVALID_METADATA_UPDATE: i8 = 124;
+const ERROR_INVALID_TYPE_ARGS: i8 = 125;
+const ERROR_DELETION_LOCKED: i8 = 126;
+const ERROR_DELETION_FORBIDDEN: i8 = 103;
+const ERROR_INVALID_MANIFEST: i8 = 127;
+const ERROR_EMPTY_FILENAME: i8 = 91;
+const ERROR_INVALID_FILENAME_ENCODING: i8 = 92;
+const ERROR_INVALID_FILENAME_CHARACTER: i8 = 93;
+const ERROR_FILENAME_TOO_LONG: i8 = 94;
+const ERROR_INVALID_CONTENT_TYPE: i8 = 95;
+const ERROR_CONTENT_TYPE_TOO_LONG: i8 = 96;
+const ERROR_SHARED_WITNESS: i8 = 81;
+const ERROR_INVALID_WITNESS_ARGS: i8 = 82;
+const ERROR_INVALID_CONCAT_SOURCE: i8 = 71;
+const ERROR_HASHER_DIGEST_MISMATCH: i8 = 61;
+const ERROR_ENCODING: i8 = -4;
+const ERROR_WITNESS_INDEX_REPEATED: i8 = -102;
+const ERROR_WITNESS_INDEX_BACKWARD: i8 = -103;
+const ERROR_WITNESS_INDEX_OUT_OF_BOUND: i8 = -104;
+const ERROR_WITNESS_CHAIN_TOO_LONG: i8 = -105;
+// errors of the hasher, see crates/ckbfs-hasher/src/error.rs, CKBFS exits with them as they are
+const ERROR_VALIDATE_FAILURE: i8 = -101;
+const ERROR_INVALID_STATE: i8 = -106;
+const ERROR_UNRECOVERABLE: i8 = -107;
+const ERROR_INVALID_ARG: i8 = -110;
+const ERROR_INVALID_ARG_LENGTH: i8 = -111;
+const ERROR_MISSING_ARGS: i8 = -112;
+const ERROR_UNKNOWN_MODE: i8 = -113;
+const ERROR_INVALID_SOURCE: i8 = -114;
+const ERROR_INVALID_OFFSET: i8 = -115;
+const ERROR_WITNESS_MISSING: i8 = -116;
+const ERROR_INVALID_WITNESS: i8 = -117;
+const ERROR_INVALID_WITNESS_VERSION: i8 = -118;
+const ERROR_INVALID_WITNESS_INDEXES: i8 = -119;
+const MAX_WITNESS_CHAIN_LENGTH: u32 = 1024;
+const ERROR_INVALID_MIGRATION: i8 = 117;
 
 pub fn build_type_id(first_input: &CellInput, out_index: usize) -> [u8; 32] {
     let mut blake2b = Blake2bBuilder::new(32)
@@ -20,6 +85,380 @@ pub fn build_type_id(first_input: &CellInput, out_index: usize) -> [u8; 32] {
     verify_id
 }
 
+// Head Witness: "CKBFS" + version + previous position + previous checksum + next index + content
+pub fn build_head_witness(
+    previous_tx_hash: &[u8; 32],
+    previous_index: u32,
+    previous_checksum: u32,
+    next_index: u32,
+    content: &[u8],
+) -> Bytes {
+    let mut witness = Vec::new();
+    witness.extend_from_slice(b"CKBFS");
+    witness.push(0x03);
+    witness.extend_from_slice(previous_tx_hash);
+    witness.extend_from_slice(&previous_index.to_le_bytes());
+    witness.extend_from_slice(&previous_checksum.to_le_bytes());
+    witness.extend_from_slice(&next_index.to_le_bytes());
+    witness.extend_from_slice(content);
+    Bytes::from(witness)
+}
+
+// Middle / Tail Witness: next index + content
+pub fn build_chain_witness(next_index: u32, content: &[u8]) -> Bytes {
+    let mut witness = next_index.to_le_bytes().to_vec();
+    witness.extend_from_slice(content);
+    Bytes::from(witness)
+}
+
+// CKBFS payload carried in WitnessArgs, next to a lock signature placeholder
+pub fn wrap_witness(lock: &[u8], input_type: Option<Bytes>, output_type: Option<Bytes>) -> Bytes {
+    WitnessArgs::new_builder()
+        .lock(Some(Bytes::copy_from_slice(lock)).pack())
+        .input_type(input_type.pack())
+        .output_type(output_type.pack())
+        .build()
+        .as_bytes()
+}
+
+// appends fields to a molecule table, like newer CKBFSData layouts do
+pub fn extend_table(table: &[u8], extra_fields: &[&[u8]]) -> Bytes {
+    let read_u32 = |offset: usize| u32::from_le_bytes(table[offset..offset + 4].try_into().unwrap()) as usize;
+    let field_count = read_u32(4) / 4 - 1;
+    let mut fields: Vec<&[u8]> = (0..field_count)
+        .map(|i| {
+            let end = if i + 1 == field_count { table.len() } else { read_u32(8 + i * 4) };
+            &table[read_u32(4 + i * 4)..end]
+        })
+        .collect();
+    fields.extend_from_slice(extra_fields);
+    Bytes::from(encode_dynamic(&fields))
+}
+
+// molecule table or dynvec layout: total size, item offsets, items
+pub fn encode_dynamic(fields: &[&[u8]]) -> Vec<u8> {
+    let header_size = 4 + fields.len() * 4;
+    let total_size = header_size + fields.iter().map(|field| field.len()).sum::<usize>();
+    let mut result = (total_size as u32).to_le_bytes().to_vec();
+    let mut offset = header_size;
+    for field in fields {
+        result.extend_from_slice(&(offset as u32).to_le_bytes());
+        offset += field.len();
+    }
+    for field in fields {
+        result.extend_from_slice(field);
+    }
+    result
+}
+
+// CKBFS v3 cell data of `hello_data`, extended up to the last field set, fields before it are
+// left empty, e.g. `HelloData::new(checksum).owner(&owner).build()`
+pub struct HelloData {
+    checksum: u32,
+    extra_fields: Vec<Vec<u8>>,
+}
+
+impl HelloData {
+    // indexes of the extended fields, as in contracts/ckbfs/src/fields.rs
+    const FLAGS: usize = 4;
+    const APPENDERS: usize = 5;
+    const OWNER: usize = 6;
+    const CONTENT_HASH: usize = 7;
+    const LENGTH: usize = 8;
+    const METADATA: usize = 9;
+    const HASHER_DIGEST: usize = 10;
+
+    pub fn new(checksum: u32) -> Self {
+        Self {
+            checksum,
+            extra_fields: Vec::new(),
+        }
+    }
+
+    // sets any bytes, even malformed ones, as the field at `index`
+    pub fn field(mut self, index: usize, value: &[u8]) -> Self {
+        let empty = |index| match index {
+            Self::FLAGS => vec![0],
+            Self::APPENDERS => Byte32Vec::default().as_slice().to_vec(),
+            Self::METADATA => encode_dynamic(&[]),
+            _ => Vec::new(),
+        };
+        while self.extra_fields.len() <= index - Self::FLAGS {
+            self.extra_fields.push(empty(Self::FLAGS + self.extra_fields.len()));
+        }
+        self.extra_fields[index - Self::FLAGS] = value.to_vec();
+        self
+    }
+
+    pub fn flags(self, flags: u8) -> Self {
+        self.field(Self::FLAGS, &[flags])
+    }
+
+    pub fn appenders(self, appenders: &[Byte32]) -> Self {
+        let appenders = Byte32Vec::new_builder().set(appenders.to_vec()).build();
+        self.field(Self::APPENDERS, appenders.as_slice())
+    }
+
+    pub fn owner(self, owner: &Byte32) -> Self {
+        self.field(Self::OWNER, owner.as_slice())
+    }
+
+    pub fn content_hash(self, content_hash: Option<[u8; 32]>) -> Self {
+        self.field(Self::CONTENT_HASH, content_hash.as_ref().map_or(&[], |hash| &hash[..]))
+    }
+
+    pub fn length(self, length: u64) -> Self {
+        self.field(Self::LENGTH, &length.to_le_bytes())
+    }
+
+    // metadata entries of (key, value, policy)
+    pub fn metadata(self, entries: &[(&str, &str, u8)]) -> Self {
+        let bytes = |value: &str| [&(value.len() as u32).to_le_bytes()[..], value.as_bytes()].concat();
+        let entries: Vec<Vec<u8>> = entries
+            .iter()
+            .map(|(key, value, policy)| encode_dynamic(&[&bytes(key), &bytes(value), &[*policy]]))
+            .collect();
+        self.field(Self::METADATA, &encode_dynamic(&entries.iter().map(Vec::as_slice).collect::<Vec<_>>()))
+    }
+
+    pub fn hasher_digest(self, hasher_digest: Option<[u8; 32]>) -> Self {
+        self.field(Self::HASHER_DIGEST, hasher_digest.as_ref().map_or(&[], |digest| &digest[..]))
+    }
+
+    pub fn build(self) -> Bytes {
+        let data: CKBFSData = hello_data(self.checksum).into();
+        let extra_fields: Vec<&[u8]> = self.extra_fields.iter().map(Vec::as_slice).collect();
+        extend_table(data.as_slice(), &extra_fields)
+    }
+}
+
+// Rule 27: blake2b(content), or blake2b(previous_hash || content) when appending
+pub fn content_hash(previous_hash: Option<[u8; 32]>, content: &[u8]) -> [u8; 32] {
+    let mut blake2b = Blake2bBuilder::new(32)
+        .personal(b"ckb-default-hash")
+        .build();
+    if let Some(previous_hash) = previous_hash {
+        blake2b.update(&previous_hash);
+    }
+    blake2b.update(content);
+    let mut hash = [0u8; 32];
+    blake2b.finalize(&mut hash);
+    hash
+}
+
+pub fn adler32(content: &[u8]) -> u32 {
+    adler::adler32_slice(content)
+}
+
+// Rule 13: hasher.recover_from(previous_checksum).update(new_content_bytes)
+pub fn adler32_append(previous_checksum: u32, content: &[u8]) -> u32 {
+    let mut hasher = adler::Adler32::from_checksum(previous_checksum);
+    hasher.write_slice(content);
+    hasher.checksum()
+}
+
+const CRC32C: crc::Crc<u32> = crc::Crc::<u32>::new(&crc::CRC_32_ISCSI);
+
+pub fn crc32c(content: &[u8]) -> u32 {
+    CRC32C.checksum(content)
+}
+
+// same as adler32_append, the register is recovered from the previous checksum
+pub fn crc32c_append(previous_checksum: u32, content: &[u8]) -> u32 {
+    let initial = (previous_checksum ^ crc::CRC_32_ISCSI.xorout).reverse_bits();
+    let mut digest = CRC32C.digest_with_initial(initial);
+    digest.update(content);
+    digest.finalize()
+}
+
+pub fn assert_script_error(err: Error, code: i8) {
+    let message = err.to_string();
+    assert!(
+        message.contains(&format!("error code {} ", code)),
+        "expected error code {}, got: {}",
+        code,
+        message
+    );
+}
+
+// Deploys ckbfs, ckb-adler32 and an always-success lock
+struct CKBFSEnv {
+    context: Context,
+    ckbfs_out_point: OutPoint,
+    lock_script: Script,
+    adler32_dep: CellDep,
+}
+
+impl CKBFSEnv {
+    fn new() -> Self {
+        Self::with_contract("ckbfs")
+    }
+
+    // `contract` is the binary of the CKBFS type script, e.g. a build with other features
+    fn with_contract(contract: &str) -> Self {
+        let mut context = Context::default();
+        let contract_bin: Bytes = Loader::default().load_binary(contract);
+        let adler32_bin: Bytes = Loader::default().load_binary("ckb-adler32");
+        let always_success_outpoint = context.deploy_cell(ALWAYS_SUCCESS.clone());
+        let ckbfs_out_point = context.deploy_cell(contract_bin);
+        let adler32_outpoint = context.deploy_cell(adler32_bin);
+        let lock_script = context
+            .build_script(&always_success_outpoint, Bytes::from(vec![42]))
+            .expect("script");
+        let adler32_dep = CellDep::new_builder()
+            .out_point(adler32_outpoint)
+            .dep_type(DepType::Code.into())
+            .build();
+        Self {
+            context,
+            ckbfs_out_point,
+            lock_script,
+            adler32_dep,
+        }
+    }
+
+    fn create_input(&mut self) -> CellInput {
+        let out_point = self.context.create_cell(
+            CellOutput::new_builder()
+                .capacity(1000u64.pack())
+                .lock(self.lock_script.clone())
+                .build(),
+            Bytes::new(),
+        );
+        CellInput::new_builder().previous_output(out_point).build()
+    }
+
+    fn ckbfs_output(&mut self, args: Bytes) -> CellOutput {
+        let type_script = self
+            .context
+            .build_script(&self.ckbfs_out_point, args)
+            .expect("script");
+        CellOutput::new_builder()
+            .capacity(500u64.pack())
+            .lock(self.lock_script.clone())
+            .type_(ScriptOpt::new_builder().set(Some(type_script)).build())
+            .build()
+    }
+
+    fn create_ckbfs_cell(&mut self, args: Bytes, data: CKBFSDataNative) -> OutPoint {
+        let output = self.ckbfs_output(args);
+        let data: CKBFSData = data.into();
+        self.context.create_cell(output, data.as_bytes())
+    }
+
+    // Publishes a single CKBFS file, the head witness is placed at index 0
+    fn publish_tx(&mut self, data: CKBFSDataNative, witnesses: Vec<Bytes>) -> TransactionView {
+        self.publish_tx_with_deps(data, witnesses, vec![])
+    }
+
+    fn publish_tx_with_deps(
+        &mut self,
+        data: CKBFSDataNative,
+        witnesses: Vec<Bytes>,
+        cell_deps: Vec<CellDep>,
+    ) -> TransactionView {
+        let data: CKBFSData = data.into();
+        self.publish_tx_with_args(data.as_bytes(), witnesses, cell_deps, &[])
+    }
+
+    // Type args are the type id followed by `extra_args`, e.g. the hasher
+    fn publish_tx_with_args(
+        &mut self,
+        data: Bytes,
+        witnesses: Vec<Bytes>,
+        cell_deps: Vec<CellDep>,
+        extra_args: &[u8],
+    ) -> TransactionView {
+        let input = self.create_input();
+        let mut args = build_type_id(&input, 0).to_vec();
+        args.extend_from_slice(extra_args);
+        let output = self.ckbfs_output(args.into());
+        let tx = TransactionBuilder::default()
+            .input(input)
+            .output(output)
+            .output_data(data.pack())
+            .cell_dep(self.adler32_dep.clone())
+            .cell_deps(cell_deps)
+            .witnesses(witnesses.into_iter().map(|w| w.pack()).collect::<Vec<_>>())
+            .build();
+        self.context.complete_tx(tx)
+    }
+
+    // Appends to or transfers an existing CKBFS cell, the head witness is placed at index 0
+    fn update_tx(
+        &mut self,
+        input_out_point: OutPoint,
+        data: CKBFSDataNative,
+        witnesses: Vec<Bytes>,
+    ) -> TransactionView {
+        let data: CKBFSData = data.into();
+        self.update_tx_with_raw_data(input_out_point, data.as_bytes(), witnesses)
+    }
+
+    fn update_tx_with_raw_data(
+        &mut self,
+        input_out_point: OutPoint,
+        data: Bytes,
+        witnesses: Vec<Bytes>,
+    ) -> TransactionView {
+        let (input_cell, _) = self.context.get_cell(&input_out_point).expect("ckbfs cell");
+        let tx = TransactionBuilder::default()
+            .input(CellInput::new_builder().previous_output(input_out_point).build())
+            .output(input_cell)
+            .output_data(data.pack())
+            .cell_dep(self.adler32_dep.clone())
+            .witnesses(witnesses.into_iter().map(|w| w.pack()).collect::<Vec<_>>())
+            .build();
+        self.context.complete_tx(tx)
+    }
+
+    // Publishes one CKBFS file per head witness index in a single transaction
+    fn batch_publish_tx(&mut self, checksum: u32, heads: &[u32], witnesses: Vec<Bytes>) -> TransactionView {
+        let input = self.create_input();
+        let mut tx = TransactionBuilder::default()
+            .input(input.clone())
+            .cell_dep(self.adler32_dep.clone())
+            .witnesses(witnesses.into_iter().map(|w| w.pack()).collect::<Vec<_>>());
+        for (output_index, head) in heads.iter().enumerate() {
+            let data: CKBFSData = CKBFSDataNative {
+                index: *head,
+                ..hello_data(checksum)
+            }
+            .into();
+            let output = self.ckbfs_output(build_type_id(&input, output_index).to_vec().into());
+            tx = tx.output(output).output_data(data.as_bytes().pack());
+        }
+        self.context.complete_tx(tx.build())
+    }
+
+    // Consumes a CKBFS cell without a CKBFS output
+    fn destroy_tx(&mut self, input_out_point: OutPoint, since: u64) -> TransactionView {
+        let output = CellOutput::new_builder()
+            .capacity(500u64.pack())
+            .lock(self.lock_script.clone())
+            .build();
+        let tx = TransactionBuilder::default()
+            .input(
+                CellInput::new_builder()
+                    .previous_output(input_out_point)
+                    .since(since.pack())
+                    .build(),
+            )
+            .output(output)
+            .output_data(Bytes::new().pack())
+            .build();
+        self.context.complete_tx(tx)
+    }
+}
+
+fn hello_data(checksum: u32) -> CKBFSDataNative {
+    CKBFSDataNative {
+        index: 0,
+        checksum,
+        content_type: "plain/text".to_string(),
+        filename: "Hello.txt".to_string(),
+    }
+}
 
 // generated unit test for contract ckbfs
 #[test]
@@ -163,3 +602,1967 @@ fn test_ckb_adler32() {
         .expect("pass verification");
     println!("consume cycles: {}", cycles);
 }
+
+#[test]
+fn test_publish_with_invalid_witness_header() {
+    let mut env = CKBFSEnv::new();
+    let mut witness = build_head_witness(&[0u8; 32], 0, 0, 0, b"HELLO CKBFS").to_vec();
+    witness[0..5].copy_from_slice(b"CKBFZ");
+    let tx = env.publish_tx(hello_data(0x11ea02fe), vec![Bytes::from(witness)]);
+
+    // without the "CKBFS" header, the witness is read as a WitnessArgs, which it is not
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_INVALID_WITNESS_ARGS);
+
+    // a WitnessArgs carrying a payload without the "CKBFS" header
+    let mut payload = build_head_witness(&[0u8; 32], 0, 0, 0, b"HELLO CKBFS").to_vec();
+    payload[0..5].copy_from_slice(b"CKBFZ");
+    let witness = wrap_witness(&[0u8; 65], None, Some(payload.into()));
+    let tx = env.publish_tx(hello_data(0x11ea02fe), vec![witness]);
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_INVALID_WITNESS_HEADER);
+}
+
+#[test]
+fn test_publish_with_invalid_witness_version() {
+    let mut env = CKBFSEnv::new();
+    let mut witness = build_head_witness(&[0u8; 32], 0, 0, 0, b"HELLO CKBFS").to_vec();
+    witness[5] = 0x00;
+    let tx = env.publish_tx(hello_data(0x11ea02fe), vec![Bytes::from(witness)]);
+
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_INVALID_VERSION);
+}
+
+#[test]
+fn test_transfer_with_invalid_witness_header() {
+    let mut env = CKBFSEnv::new();
+    let type_id = [7u8; 32];
+    let input_out_point = env.create_ckbfs_cell(type_id.to_vec().into(), hello_data(0x11ea02fe));
+    let output = env.ckbfs_output(type_id.to_vec().into());
+    let data: CKBFSData = hello_data(0x11ea02fe).into();
+
+    let mut witness = build_head_witness(
+        &input_out_point.tx_hash().unpack(),
+        0,
+        0x11ea02fe,
+        0,
+        &[],
+    )
+    .to_vec();
+    witness[0..5].copy_from_slice(b"ckbfs");
+    let tx = TransactionBuilder::default()
+        .input(CellInput::new_builder().previous_output(input_out_point).build())
+        .output(output)
+        .output_data(data.as_slice().pack())
+        .cell_dep(env.adler32_dep.clone())
+        .witness(Bytes::from(witness).pack())
+        .build();
+    let tx = env.context.complete_tx(tx);
+
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_INVALID_WITNESS_ARGS);
+}
+
+#[test]
+fn test_append_and_transfer() {
+    let mut env = CKBFSEnv::new();
+    let type_id = [7u8; 32];
+    let checksum = adler32(b"HELLO CKBFS");
+    let input_out_point = env.create_ckbfs_cell(type_id.to_vec().into(), hello_data(checksum));
+    let previous_tx_hash: [u8; 32] = input_out_point.tx_hash().unpack();
+
+    // append
+    let appended_checksum = adler32_append(checksum, b" AGAIN");
+    assert_eq!(appended_checksum, adler32(b"HELLO CKBFS AGAIN"));
+    let witness = build_head_witness(&previous_tx_hash, 0, checksum, 0, b" AGAIN");
+    let tx = env.update_tx(input_out_point.clone(), hello_data(appended_checksum), vec![witness]);
+    env.context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+
+    // transfer
+    let witness = build_head_witness(&previous_tx_hash, 0, checksum, 0, &[]);
+    let tx = env.update_tx(input_out_point, hello_data(checksum), vec![witness]);
+    env.context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+}
+
+#[test]
+fn test_witnesses_in_witness_args() {
+    let mut env = CKBFSEnv::new();
+    // publish, head and tail witnesses are both carried in output_type
+    let witnesses = vec![
+        wrap_witness(
+            &[0u8; 65],
+            None,
+            Some(build_head_witness(&[0u8; 32], 0, 0, 1, b"HELLO")),
+        ),
+        wrap_witness(&[], None, Some(build_chain_witness(0, b" CKBFS"))),
+    ];
+    let tx = env.publish_tx(hello_data(adler32(b"HELLO CKBFS")), witnesses);
+    env.context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+
+    // a wrapped head witness can not be followed by raw middle witnesses, the hasher
+    // fails on the malformed witness instead of reporting a checksum
+    let witnesses = vec![
+        wrap_witness(
+            &[0u8; 65],
+            None,
+            Some(build_head_witness(&[0u8; 32], 0, 0, 1, b"HELLO")),
+        ),
+        build_chain_witness(0, b" CKBFS"),
+    ];
+    let tx = env.publish_tx(hello_data(adler32(b"HELLO CKBFS")), witnesses);
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_INVALID_WITNESS);
+
+    // transfer, the head witness is carried in input_type
+    let checksum = adler32(b"HELLO CKBFS");
+    let input_out_point = env.create_ckbfs_cell([7u8; 32].to_vec().into(), hello_data(checksum));
+    let previous_tx_hash: [u8; 32] = input_out_point.tx_hash().unpack();
+    let witness = wrap_witness(
+        &[0u8; 65],
+        Some(build_head_witness(&previous_tx_hash, 0, checksum, 0, &[])),
+        None,
+    );
+    let tx = env.update_tx(input_out_point.clone(), hello_data(checksum), vec![witness]);
+    env.context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+
+    // a WitnessArgs without CKBFS payload is not a head witness
+    let witness = wrap_witness(&[0u8; 65], None, None);
+    let tx = env.update_tx(input_out_point, hello_data(checksum), vec![witness]);
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_INVALID_WITNESS_ARGS);
+}
+
+#[test]
+fn test_append_with_forged_previous_checksum() {
+    let mut env = CKBFSEnv::new();
+    let type_id = [7u8; 32];
+    let checksum = adler32(b"HELLO CKBFS");
+    let input_out_point = env.create_ckbfs_cell(type_id.to_vec().into(), hello_data(checksum));
+    let previous_tx_hash: [u8; 32] = input_out_point.tx_hash().unpack();
+
+    // the forged history hashes consistently, only the binding to the input cell catches it
+    let forged_checksum = adler32(b"FORGED HISTORY");
+    let appended_checksum = adler32_append(forged_checksum, b" AGAIN");
+    let witness = build_head_witness(&previous_tx_hash, 0, forged_checksum, 0, b" AGAIN");
+    let tx = env.update_tx(input_out_point, hello_data(appended_checksum), vec![witness]);
+
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_INVALID_PREVIOUS_CHECKSUM);
+}
+
+#[test]
+fn test_transfer_with_forged_previous_checksum() {
+    let mut env = CKBFSEnv::new();
+    let type_id = [7u8; 32];
+    let checksum = adler32(b"HELLO CKBFS");
+    let input_out_point = env.create_ckbfs_cell(type_id.to_vec().into(), hello_data(checksum));
+    let previous_tx_hash: [u8; 32] = input_out_point.tx_hash().unpack();
+
+    let witness = build_head_witness(&previous_tx_hash, 0, adler32(b"FORGED"), 0, &[]);
+    let tx = env.update_tx(input_out_point, hello_data(checksum), vec![witness]);
+
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_INVALID_PREVIOUS_CHECKSUM);
+}
+
+#[test]
+fn test_publish_with_previous_checksum() {
+    let mut env = CKBFSEnv::new();
+    let witness = build_head_witness(&[0u8; 32], 0, adler32(b"FORGED"), 0, b"HELLO CKBFS");
+    let checksum = adler32_append(adler32(b"FORGED"), b"HELLO CKBFS");
+    let tx = env.publish_tx(hello_data(checksum), vec![witness]);
+
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_INVALID_PREVIOUS_CHECKSUM);
+}
+
+#[test]
+fn test_publish_with_checksum_mismatch() {
+    let mut env = CKBFSEnv::new();
+    let witness = build_head_witness(&[0u8; 32], 0, 0, 0, b"HELLO CKBFS");
+    let tx = env.publish_tx(hello_data(adler32(b"HELLO CKB")), vec![witness]);
+
+    // the hasher is spawned, so its failure comes back as the contract's own error code
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_CHECKSUM_MISMATCH);
+}
+
+#[test]
+fn test_publish_with_witness_chain() {
+    let mut env = CKBFSEnv::new();
+    // witnesses may be skipped, as long as the chain moves forward
+    let witnesses = vec![
+        build_head_witness(&[0u8; 32], 0, 0, 2, b"HELLO"),
+        Bytes::from_static(b"NOT CKBFS"),
+        build_chain_witness(3, b" CKBFS"),
+        build_chain_witness(0, b" CHAIN"),
+    ];
+    let tx = env.publish_tx(hello_data(adler32(b"HELLO CKBFS CHAIN")), witnesses);
+    env.context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+}
+
+#[test]
+fn test_publish_large_file() {
+    let mut env = CKBFSEnv::new();
+    // parts are larger than the hasher's heap, they can only be hashed window by window
+    let part = vec![b'A'; 600 * 1024];
+    let witnesses = vec![
+        build_head_witness(&[0u8; 32], 0, 0, 1, &part),
+        build_chain_witness(0, &part),
+    ];
+    let checksum = adler32_append(adler32(&part), &part);
+    let tx = env.publish_tx(hello_data(checksum), witnesses);
+    env.context
+        .verify_tx(&tx, MAX_CYCLES * 10)
+        .expect("pass verification");
+}
+
+#[test]
+fn test_publish_with_witness_cycle() {
+    let mut env = CKBFSEnv::new();
+    let witnesses = vec![
+        build_head_witness(&[0u8; 32], 0, 0, 1, b"HELLO"),
+        build_chain_witness(2, b" CKBFS"),
+        build_chain_witness(1, b" AGAIN"),
+    ];
+    let tx = env.publish_tx(hello_data(adler32(b"HELLO CKBFS")), witnesses);
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_WITNESS_INDEX_REPEATED);
+
+    // a witness pointing to itself is a cycle as well
+    let witnesses = vec![
+        build_head_witness(&[0u8; 32], 0, 0, 1, b"HELLO"),
+        build_chain_witness(1, b" CKBFS"),
+    ];
+    let tx = env.publish_tx(hello_data(adler32(b"HELLO CKBFS")), witnesses);
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_WITNESS_INDEX_REPEATED);
+}
+
+#[test]
+fn test_publish_with_backward_witness_index() {
+    let mut env = CKBFSEnv::new();
+    let witnesses = vec![
+        build_head_witness(&[0u8; 32], 0, 0, 2, b"HELLO"),
+        build_chain_witness(0, b" CKBFS"),
+        build_chain_witness(1, b" AGAIN"),
+    ];
+    let tx = env.publish_tx(hello_data(adler32(b"HELLO AGAIN CKBFS")), witnesses);
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_WITNESS_INDEX_BACKWARD);
+}
+
+#[test]
+fn test_publish_with_out_of_bound_witness_index() {
+    let mut env = CKBFSEnv::new();
+    let witnesses = vec![
+        build_head_witness(&[0u8; 32], 0, 0, 1, b"HELLO"),
+        build_chain_witness(5, b" CKBFS"),
+    ];
+    let tx = env.publish_tx(hello_data(adler32(b"HELLO CKBFS")), witnesses);
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_WITNESS_INDEX_OUT_OF_BOUND);
+}
+
+#[test]
+fn test_publish_with_too_long_witness_chain() {
+    let mut env = CKBFSEnv::new();
+    let mut witnesses = vec![build_head_witness(&[0u8; 32], 0, 0, 1, b"")];
+    for index in 1..=MAX_WITNESS_CHAIN_LENGTH {
+        witnesses.push(build_chain_witness(index + 1, b"A"));
+    }
+    witnesses.push(build_chain_witness(0, b"A"));
+    let content = vec![b'A'; witnesses.len() - 1];
+    let tx = env.publish_tx(hello_data(adler32(&content)), witnesses);
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_WITNESS_CHAIN_TOO_LONG);
+}
+
+#[test]
+fn test_publish_with_hasher_by_type() {
+    let mut env = CKBFSEnv::new();
+    // deploy the hasher with a type script, so it can be upgraded under the same type hash
+    let hasher_type = env
+        .lock_script
+        .clone()
+        .as_builder()
+        .args(Bytes::from(vec![7]).pack())
+        .build();
+    let hasher_out_point = env.context.create_cell(
+        CellOutput::new_builder()
+            .capacity(100_000u64.pack())
+            .lock(env.lock_script.clone())
+            .type_(Some(hasher_type.clone()).pack())
+            .build(),
+        Loader::default().load_binary("ckb-adler32"),
+    );
+    let hasher_type_hash = hasher_type.calc_script_hash();
+    let witness = build_head_witness(&[0u8; 32], 0, 0, 0, b"HELLO CKBFS");
+    let data: CKBFSData = hello_data(adler32(b"HELLO CKBFS")).into();
+    let data = data.as_bytes();
+
+    let mut args = hasher_type_hash.as_slice().to_vec();
+    args.push(1);
+    let tx = env.publish_tx_with_args(
+        data.clone(),
+        vec![witness.clone()],
+        vec![code_dep(hasher_out_point)],
+        &args,
+    );
+    env.context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+
+    // type hash is not a data hash
+    args[32] = 2;
+    let tx = env.publish_tx_with_args(data.clone(), vec![witness.clone()], vec![], &args);
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_NO_CHECKSUM_HASHER_FOUND);
+
+    args[32] = 3;
+    let tx = env.publish_tx_with_args(data, vec![witness], vec![], &args);
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_INVALID_HASHER_HASH_TYPE);
+}
+
+fn code_dep(out_point: OutPoint) -> CellDep {
+    CellDep::new_builder()
+        .out_point(out_point)
+        .dep_type(DepType::Code.into())
+        .build()
+}
+
+#[test]
+fn test_fork_from_cell_dep() {
+    let mut env = CKBFSEnv::new();
+    let checksum = adler32(b"HELLO CKBFS");
+    let source_out_point = env.create_ckbfs_cell([9u8; 32].to_vec().into(), hello_data(checksum));
+    let source_tx_hash: [u8; 32] = source_out_point.tx_hash().unpack();
+
+    let forked_checksum = adler32_append(checksum, b" FORKED");
+    let witness = build_head_witness(&source_tx_hash, 0, checksum, 0, b" FORKED");
+    let tx = env.publish_tx_with_deps(
+        hello_data(forked_checksum),
+        vec![witness],
+        vec![code_dep(source_out_point)],
+    );
+    env.context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+}
+
+#[test]
+fn test_fork_with_mismatched_source() {
+    let mut env = CKBFSEnv::new();
+    let checksum = adler32(b"HELLO CKBFS");
+    let source_out_point = env.create_ckbfs_cell([9u8; 32].to_vec().into(), hello_data(checksum));
+    let source_tx_hash: [u8; 32] = source_out_point.tx_hash().unpack();
+
+    // the source checksum claimed by the head witness does not exist in CellDeps
+    let forged_checksum = adler32(b"FORGED");
+    let forked_checksum = adler32_append(forged_checksum, b" FORKED");
+    let witness = build_head_witness(&source_tx_hash, 0, forged_checksum, 0, b" FORKED");
+    let tx = env.publish_tx_with_deps(
+        hello_data(forked_checksum),
+        vec![witness],
+        vec![code_dep(source_out_point)],
+    );
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_INVALID_FORK_SOURCE);
+
+    // fork source is not referenced at all
+    let witness = build_head_witness(&source_tx_hash, 0, checksum, 0, b" FORKED");
+    let tx = env.publish_tx(hello_data(adler32_append(checksum, b" FORKED")), vec![witness]);
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_INVALID_FORK_SOURCE);
+}
+
+#[test]
+fn test_fork_with_forged_position() {
+    let mut env = CKBFSEnv::new();
+    let checksum = adler32(b"HELLO CKBFS");
+    let output = env.ckbfs_output([9u8; 32].to_vec().into());
+    let source_out_point = env.context.create_cell(output, HelloData::new(checksum).length(11).build());
+    let other_checksum = adler32(b" OTHER FILE");
+    let output = env.ckbfs_output([8u8; 32].to_vec().into());
+    let other_out_point = env.context.create_cell(output, HelloData::new(other_checksum).length(11).build());
+    let other_tx_hash: [u8; 32] = other_out_point.tx_hash().unpack();
+    let deps = vec![code_dep(source_out_point), code_dep(other_out_point)];
+
+    // the source is in CellDeps, but the previous position names the transaction of another cell dep
+    let forked_checksum = adler32_append(checksum, b" FORKED");
+    let witness = build_head_witness(&other_tx_hash, 0, checksum, 0, b" FORKED");
+    let tx = env.publish_tx_with_deps(hello_data(forked_checksum), vec![witness], deps.clone());
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_INVALID_FORK_SOURCE);
+
+    // same for a concatenation, A is claimed at the position of B
+    let witness = build_concat_witness((&other_tx_hash, 0, checksum), (&other_tx_hash, 0, other_checksum));
+    let data = HelloData::new(adler32(b"HELLO CKBFS OTHER FILE")).length(22).build();
+    let tx = env.publish_tx_with_args(data, vec![witness], deps, &[]);
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_INVALID_CONCAT_SOURCE);
+}
+
+#[test]
+fn test_fork_into_ephemeral_file() {
+    let mut env = CKBFSEnv::new();
+    let checksum = adler32(b"HELLO CKBFS");
+    let output = env.ckbfs_output([9u8; 32].to_vec().into());
+    let source_out_point = env.context.create_cell(output, HelloData::new(checksum).length(11).build());
+    let source_tx_hash: [u8; 32] = source_out_point.tx_hash().unpack();
+    let deps = vec![code_dep(source_out_point)];
+
+    // file flags need the hasher named, the default one is the same hasher as the source's
+    let mut args = CellOutput::calc_data_hash(&Loader::default().load_binary("ckb-adler32"))
+        .as_slice()
+        .to_vec();
+    args.extend_from_slice(&[2, 1]);
+    let forked_checksum = adler32_append(checksum, b" FORKED");
+    let witness = build_head_witness(&source_tx_hash, 0, checksum, 0, b" FORKED");
+    let data: CKBFSData = hello_data(forked_checksum).into();
+    let tx = env.publish_tx_with_args(data.as_bytes(), vec![witness], deps.clone(), &args);
+    env.context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+
+    let witness = build_concat_witness((&source_tx_hash, 0, checksum), (&source_tx_hash, 0, checksum));
+    let data = HelloData::new(adler32(b"HELLO CKBFSHELLO CKBFS")).length(22).build();
+    let tx = env.publish_tx_with_args(data, vec![witness], deps, &args);
+    env.context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+}
+
+// v2 witness: "CKBFS" + 0x00 + content
+fn build_v2_witness(content: &[u8]) -> Bytes {
+    let mut witness = b"CKBFS\x00".to_vec();
+    witness.extend_from_slice(content);
+    Bytes::from(witness)
+}
+
+fn hello_data_v2(
+    indexes: Vec<u32>,
+    checksum: u32,
+    backlinks: Vec<BackLinkNative>,
+) -> Bytes {
+    let data: CKBFSDataV2 = CKBFSDataNativeV2 {
+        indexes,
+        checksum,
+        content_type: "plain/text".to_string(),
+        filename: "Hello.txt".to_string(),
+        backlinks,
+    }
+    .into();
+    data.as_bytes()
+}
+
+#[test]
+fn test_v2_publish() {
+    let mut env = CKBFSEnv::new();
+    let input = env.create_input();
+    let type_id = build_type_id(&input, 0);
+    let output = env.ckbfs_output(type_id.to_vec().into());
+    let checksum = adler32(b"HELLO CKBFS");
+    let tx = TransactionBuilder::default()
+        .input(input)
+        .output(output)
+        .output_data(hello_data_v2(vec![1, 2], checksum, vec![]).pack())
+        .cell_dep(env.adler32_dep.clone())
+        .witness(Bytes::new().pack())
+        .witness(build_v2_witness(b"HELLO").pack())
+        .witness(build_v2_witness(b" CKBFS").pack())
+        .build();
+    let tx = env.context.complete_tx(tx);
+    env.context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+}
+
+#[test]
+fn test_v2_publish_with_invalid_witness_version() {
+    let mut env = CKBFSEnv::new();
+    let input = env.create_input();
+    let type_id = build_type_id(&input, 0);
+    let output = env.ckbfs_output(type_id.to_vec().into());
+    let witness = build_head_witness(&[0u8; 32], 0, 0, 0, b"HELLO CKBFS");
+    let tx = TransactionBuilder::default()
+        .input(input)
+        .output(output)
+        .output_data(hello_data_v2(vec![0], adler32(b"HELLO CKBFS"), vec![]).pack())
+        .cell_dep(env.adler32_dep.clone())
+        .witness(witness.pack())
+        .build();
+    let tx = env.context.complete_tx(tx);
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_INVALID_VERSION);
+}
+
+#[test]
+fn test_v2_append_and_transfer() {
+    let mut env = CKBFSEnv::new();
+    let type_id = [7u8; 32];
+    let checksum = adler32(b"HELLO CKBFS");
+    let output = env.ckbfs_output(type_id.to_vec().into());
+    let input_out_point = env
+        .context
+        .create_cell(output, hello_data_v2(vec![0], checksum, vec![]));
+    let backlink = BackLinkNative {
+        indexes: vec![0],
+        checksum,
+        tx_hash: input_out_point.tx_hash().unpack(),
+    };
+
+    // append links the input cell as a backlink
+    let appended_checksum = adler32_append(checksum, b" AGAIN");
+    let output_data = hello_data_v2(vec![0], appended_checksum, vec![backlink.clone()]);
+    let tx = env.update_tx_with_raw_data(
+        input_out_point.clone(),
+        output_data,
+        vec![build_v2_witness(b" AGAIN")],
+    );
+    env.context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+
+    // transfer links the input cell as well, and carries no content
+    let output_data = hello_data_v2(vec![], checksum, vec![backlink]);
+    let tx = env.update_tx_with_raw_data(input_out_point.clone(), output_data, vec![]);
+    env.context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+
+    // dropping the history is rejected
+    let output_data = hello_data_v2(vec![0], appended_checksum, vec![]);
+    let tx = env.update_tx_with_raw_data(
+        input_out_point,
+        output_data,
+        vec![build_v2_witness(b" AGAIN")],
+    );
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_INVALID_BACKLINKS);
+}
+
+#[test]
+fn test_migrate_v2_to_v3() {
+    let mut env = CKBFSEnv::new();
+    let type_id = [7u8; 32];
+    let first_checksum = adler32(b"HELLO");
+    let checksum = adler32_append(first_checksum, b" CKBFS");
+    let first_backlink = BackLinkNative {
+        indexes: vec![1],
+        checksum: first_checksum,
+        tx_hash: [9u8; 32],
+    };
+    let output = env.ckbfs_output(type_id.to_vec().into());
+    let input_out_point = env.context.create_cell(
+        output,
+        hello_data_v2(vec![2], checksum, vec![first_backlink.clone()]),
+    );
+    let input_tx_hash: [u8; 32] = input_out_point.tx_hash().unpack();
+    let history = CKBFSDataV2::from(CKBFSDataNativeV2 {
+        indexes: vec![],
+        checksum,
+        content_type: String::new(),
+        filename: String::new(),
+        backlinks: vec![
+            first_backlink,
+            BackLinkNative {
+                indexes: vec![2],
+                checksum,
+                tx_hash: input_tx_hash,
+            },
+        ],
+    })
+    .backlinks();
+
+    // the history moves into the head witness, pointing to the latest content position
+    let witness = build_head_witness(&input_tx_hash, 2, checksum, 0, history.as_slice());
+    let tx = env.update_tx(input_out_point.clone(), hello_data(checksum), vec![witness.clone()]);
+    env.context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+
+    // checksum must be kept
+    let tx = env.update_tx(
+        input_out_point.clone(),
+        hello_data(adler32(b"HELLO")),
+        vec![witness],
+    );
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_INVALID_MIGRATION);
+
+    // the history can not be dropped
+    let witness = build_head_witness(&input_tx_hash, 2, checksum, 0, &[]);
+    let tx = env.update_tx(input_out_point, hello_data(checksum), vec![witness]);
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_INVALID_BACKLINKS);
+}
+
+#[test]
+fn test_seal_file() {
+    let mut env = CKBFSEnv::new();
+    let type_id = [7u8; 32];
+    let checksum = adler32(b"HELLO CKBFS");
+    let output = env.ckbfs_output(type_id.to_vec().into());
+    let input_out_point = env.context.create_cell(output, HelloData::new(checksum).flags(0).build());
+    let previous_tx_hash: [u8; 32] = input_out_point.tx_hash().unpack();
+
+    // finalize with a transfer, or together with the last append
+    let witness = build_head_witness(&previous_tx_hash, 0, checksum, 0, &[]);
+    let tx = env.update_tx_with_raw_data(
+        input_out_point.clone(),
+        HelloData::new(checksum).flags(1).build(),
+        vec![witness],
+    );
+    env.context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    let witness = build_head_witness(&previous_tx_hash, 0, checksum, 0, b" FINAL");
+    let tx = env.update_tx_with_raw_data(
+        input_out_point,
+        HelloData::new(adler32_append(checksum, b" FINAL")).flags(1).build(),
+        vec![witness],
+    );
+    env.context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+
+    // a sealed file can only be transferred
+    let output = env.ckbfs_output(type_id.to_vec().into());
+    let sealed_out_point = env.context.create_cell(output, HelloData::new(checksum).flags(1).build());
+    let previous_tx_hash: [u8; 32] = sealed_out_point.tx_hash().unpack();
+    let witness = build_head_witness(&previous_tx_hash, 0, checksum, 0, &[]);
+    let tx = env.update_tx_with_raw_data(
+        sealed_out_point.clone(),
+        HelloData::new(checksum).flags(1).build(),
+        vec![witness.clone()],
+    );
+    env.context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+
+    let tx = env.update_tx_with_raw_data(
+        sealed_out_point.clone(),
+        HelloData::new(checksum).flags(0).build(),
+        vec![witness],
+    );
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_FILE_SEALED);
+
+    let witness = build_head_witness(&previous_tx_hash, 0, checksum, 0, b" AGAIN");
+    let tx = env.update_tx_with_raw_data(
+        sealed_out_point,
+        HelloData::new(adler32_append(checksum, b" AGAIN")).flags(1).build(),
+        vec![witness],
+    );
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_FILE_SEALED);
+}
+
+#[test]
+fn test_append_by_appender() {
+    let mut env = CKBFSEnv::new();
+    let type_id = [7u8; 32];
+    let checksum = adler32(b"HELLO CKBFS");
+    let appender_lock = env
+        .lock_script
+        .clone()
+        .as_builder()
+        .args(Bytes::from(vec![43]).pack())
+        .build();
+    let appenders = vec![appender_lock.calc_script_hash()];
+    let output = env.ckbfs_output(type_id.to_vec().into());
+    let input_out_point = env
+        .context
+        .create_cell(output, HelloData::new(checksum).appenders(&appenders).build());
+    let previous_tx_hash: [u8; 32] = input_out_point.tx_hash().unpack();
+    let appender_input = env.context.create_cell(
+        CellOutput::new_builder()
+            .capacity(1000u64.pack())
+            .lock(appender_lock)
+            .build(),
+        Bytes::new(),
+    );
+
+    let appended_checksum = adler32_append(checksum, b" LOG");
+    let witness = build_head_witness(&previous_tx_hash, 0, checksum, 0, b" LOG");
+    let tx = env.update_tx_with_raw_data(
+        input_out_point.clone(),
+        HelloData::new(appended_checksum).appenders(&appenders).build(),
+        vec![witness],
+    );
+
+    // only the cell lock is unlocked, no appender
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_UNAUTHORIZED_APPEND);
+
+    let tx = tx
+        .as_advanced_builder()
+        .input(CellInput::new_builder().previous_output(appender_input).build())
+        .build();
+    env.context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+
+    // appenders can only be updated by transfers
+    let witness = build_head_witness(&previous_tx_hash, 0, checksum, 0, b" LOG");
+    let tx = env.update_tx_with_raw_data(
+        input_out_point.clone(),
+        HelloData::new(appended_checksum).appenders(&[]).build(),
+        vec![witness],
+    );
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_INVALID_FIELD_UPDATE);
+
+    let witness = build_head_witness(&previous_tx_hash, 0, checksum, 0, &[]);
+    let tx = env.update_tx_with_raw_data(
+        input_out_point,
+        HelloData::new(checksum).appenders(&[]).build(),
+        vec![witness],
+    );
+    env.context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+}
+
+#[test]
+fn test_append_by_appender_without_owner() {
+    let mut env = CKBFSEnv::new();
+    let type_id = [7u8; 32];
+    let checksum = adler32(b"HELLO CKBFS");
+    let lock_with_args = |env: &CKBFSEnv, args: u8| {
+        env.lock_script
+            .clone()
+            .as_builder()
+            .args(Bytes::from(vec![args]).pack())
+            .build()
+    };
+    let lock_cell = |env: &mut CKBFSEnv, lock: Script| {
+        let output = CellOutput::new_builder().capacity(1000u64.pack()).lock(lock).build();
+        let out_point = env.context.create_cell(output, Bytes::new());
+        CellInput::new_builder().previous_output(out_point).build()
+    };
+
+    // the cell lock is open, a CI bot appends, and the owner keeps control of the file
+    let owner_lock = lock_with_args(&env, 44);
+    let appender_lock = lock_with_args(&env, 43);
+    let owner = owner_lock.calc_script_hash();
+    let appenders = vec![appender_lock.calc_script_hash()];
+    let output = env.ckbfs_output(type_id.to_vec().into());
+    let input_out_point = env
+        .context
+        .create_cell(output, HelloData::new(checksum).appenders(&appenders).owner(&owner).build());
+    let previous_tx_hash: [u8; 32] = input_out_point.tx_hash().unpack();
+    let owner_input = lock_cell(&mut env, owner_lock);
+    let appender_input = lock_cell(&mut env, appender_lock);
+
+    let appended_checksum = adler32_append(checksum, b" LOG");
+    let witness = build_head_witness(&previous_tx_hash, 0, checksum, 0, b" LOG");
+    let append = |env: &mut CKBFSEnv, data: Bytes, input: &CellInput| {
+        let tx = env.update_tx_with_raw_data(input_out_point.clone(), data, vec![witness.clone()]);
+        tx.as_advanced_builder().input(input.clone()).build()
+    };
+    let appended_data = |flags| {
+        HelloData::new(appended_checksum)
+            .flags(flags)
+            .appenders(&appenders)
+            .owner(&owner)
+            .build()
+    };
+    let tx = append(&mut env, appended_data(0), &appender_input);
+    env.context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+
+    // unlocking the open cell lock alone is not enough
+    let tx = env.update_tx_with_raw_data(
+        input_out_point.clone(),
+        appended_data(0),
+        vec![witness.clone()],
+    );
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_UNAUTHORIZED_APPEND);
+
+    // the appender only adds content, it can not seal the file or take it over
+    let tx = append(&mut env, appended_data(1), &appender_input);
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_INVALID_FIELD_UPDATE);
+    let tx = append(
+        &mut env,
+        HelloData::new(appended_checksum).appenders(&appenders).owner(&appenders[0]).build(),
+        &appender_input,
+    );
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_INVALID_FIELD_UPDATE);
+    let tx = append(&mut env, appended_data(0), &appender_input);
+    let tx = tx
+        .as_advanced_builder()
+        .set_outputs(vec![tx.output(0).unwrap().as_builder().capacity(1u64.pack()).build()])
+        .build();
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_INVALID_APPEND);
+
+    // the owner appends and seals without an appender
+    let tx = append(&mut env, appended_data(1), &owner_input);
+    env.context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+
+    // only the owner can transfer
+    let witness = build_head_witness(&previous_tx_hash, 0, checksum, 0, &[]);
+    let tx = env.update_tx_with_raw_data(
+        input_out_point,
+        HelloData::new(checksum).owner(&appenders[0]).build(),
+        vec![witness],
+    );
+    let by_appender = tx.as_advanced_builder().input(appender_input.clone()).build();
+    let err = env.context.verify_tx(&by_appender, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_UNAUTHORIZED_UPDATE);
+    let by_owner = tx.as_advanced_builder().input(owner_input.clone()).build();
+    env.context
+        .verify_tx(&by_owner, MAX_CYCLES)
+        .expect("pass verification");
+
+    // and destroy an ephemeral file
+    let mut args = type_id.to_vec();
+    args.extend_from_slice(CellOutput::calc_data_hash(&Loader::default().load_binary("ckb-adler32")).as_slice());
+    args.extend_from_slice(&[2, 1]);
+    let output = env.ckbfs_output(args.into());
+    let out_point = env
+        .context
+        .create_cell(output, HelloData::new(checksum).appenders(&appenders).owner(&owner).build());
+    let tx = env.destroy_tx(out_point, 0);
+    let by_appender = tx.as_advanced_builder().input(appender_input).build();
+    let err = env.context.verify_tx(&by_appender, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_UNAUTHORIZED_UPDATE);
+    let by_owner = tx.as_advanced_builder().input(owner_input).build();
+    env.context
+        .verify_tx(&by_owner, MAX_CYCLES)
+        .expect("pass verification");
+}
+
+#[test]
+fn test_transfer_with_malformed_owner() {
+    let mut env = CKBFSEnv::new();
+    let type_id = [7u8; 32];
+    let checksum = adler32(b"HELLO CKBFS");
+    let input_out_point = env.create_ckbfs_cell(type_id.to_vec().into(), hello_data(checksum));
+    let previous_tx_hash: [u8; 32] = input_out_point.tx_hash().unpack();
+
+    // a transfer adds no content, the extended fields it writes are still checked
+    let data = HelloData::new(checksum).field(HelloData::OWNER, &[0u8; 5]).build();
+    let witness = build_head_witness(&previous_tx_hash, 0, checksum, 0, &[]);
+    let tx = env.update_tx_with_raw_data(input_out_point, data, vec![witness]);
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_ENCODING);
+}
+
+#[test]
+fn test_content_hash() {
+    let mut env = CKBFSEnv::new();
+    let checksum = adler32(b"HELLO CKBFS");
+    let hash = content_hash(None, b"HELLO CKBFS");
+
+    // publish, content hash covers all parts
+    let witnesses = vec![
+        build_head_witness(&[0u8; 32], 0, 0, 1, b"HELLO"),
+        build_chain_witness(0, b" CKBFS"),
+    ];
+    let data = HelloData::new(checksum).content_hash(Some(hash)).build();
+    let tx = env.publish_tx_with_args(data, witnesses.clone(), vec![], &[]);
+    env.context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    let data = HelloData::new(checksum).content_hash(Some([1u8; 32])).build();
+    let tx = env.publish_tx_with_args(data, witnesses, vec![], &[]);
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_CONTENT_HASH_MISMATCH);
+
+    let output = env.ckbfs_output([7u8; 32].to_vec().into());
+    let input_out_point = env
+        .context
+        .create_cell(output, HelloData::new(checksum).content_hash(Some(hash)).build());
+    let previous_tx_hash: [u8; 32] = input_out_point.tx_hash().unpack();
+
+    // append chains the hash
+    let appended_checksum = adler32_append(checksum, b" AGAIN");
+    let witness = build_head_witness(&previous_tx_hash, 0, checksum, 0, b" AGAIN");
+    let tx = env.update_tx_with_raw_data(
+        input_out_point.clone(),
+        HelloData::new(appended_checksum).content_hash(Some(content_hash(Some(hash), b" AGAIN"))).build(),
+        vec![witness.clone()],
+    );
+    env.context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+
+    for output_hash in [Some(content_hash(None, b"HELLO CKBFS AGAIN")), None] {
+        let tx = env.update_tx_with_raw_data(
+            input_out_point.clone(),
+            HelloData::new(appended_checksum).content_hash(output_hash).build(),
+            vec![witness.clone()],
+        );
+        let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+        assert_script_error(err, ERROR_CONTENT_HASH_MISMATCH);
+    }
+
+    // transfer keeps the hash
+    let witness = build_head_witness(&previous_tx_hash, 0, checksum, 0, &[]);
+    let tx = env.update_tx_with_raw_data(
+        input_out_point.clone(),
+        HelloData::new(checksum).content_hash(Some(hash)).build(),
+        vec![witness.clone()],
+    );
+    env.context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    let tx = env.update_tx_with_raw_data(
+        input_out_point,
+        HelloData::new(checksum).content_hash(Some(content_hash(Some(hash), &[]))).build(),
+        vec![witness],
+    );
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_CONTENT_HASH_MISMATCH);
+}
+
+#[test]
+fn test_content_length() {
+    let mut env = CKBFSEnv::new();
+    let checksum = adler32(b"HELLO CKBFS");
+
+    // publish, length covers all parts
+    let witnesses = vec![
+        build_head_witness(&[0u8; 32], 0, 0, 1, b"HELLO"),
+        build_chain_witness(0, b" CKBFS"),
+    ];
+    let data = HelloData::new(checksum).length(11).build();
+    let tx = env.publish_tx_with_args(data, witnesses.clone(), vec![], &[]);
+    env.context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    let data = HelloData::new(checksum).length(5).build();
+    let tx = env.publish_tx_with_args(data, witnesses, vec![], &[]);
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_LENGTH_MISMATCH);
+
+    let output = env.ckbfs_output([7u8; 32].to_vec().into());
+    let input_out_point = env
+        .context
+        .create_cell(output, HelloData::new(checksum).length(11).build());
+    let previous_tx_hash: [u8; 32] = input_out_point.tx_hash().unpack();
+
+    // append adds the new content length
+    let appended_checksum = adler32_append(checksum, b" AGAIN");
+    let witness = build_head_witness(&previous_tx_hash, 0, checksum, 0, b" AGAIN");
+    let tx = env.update_tx_with_raw_data(
+        input_out_point.clone(),
+        HelloData::new(appended_checksum).length(17).build(),
+        vec![witness.clone()],
+    );
+    env.context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    let tx = env.update_tx_with_raw_data(
+        input_out_point.clone(),
+        HelloData::new(appended_checksum).length(6).build(),
+        vec![witness],
+    );
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_LENGTH_MISMATCH);
+
+    // transfer keeps the length
+    let witness = build_head_witness(&previous_tx_hash, 0, checksum, 0, &[]);
+    let tx = env.update_tx_with_raw_data(
+        input_out_point.clone(),
+        HelloData::new(checksum).length(11).build(),
+        vec![witness.clone()],
+    );
+    env.context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    for output_data in [
+        HelloData::new(checksum).length(12).build(),
+        HelloData::new(checksum).content_hash(None).build(),
+    ] {
+        let tx =
+            env.update_tx_with_raw_data(input_out_point.clone(), output_data, vec![witness.clone()]);
+        let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+        assert_script_error(err, ERROR_LENGTH_MISMATCH);
+    }
+}
+
+#[test]
+fn test_metadata() {
+    let mut env = CKBFSEnv::new();
+    let checksum = adler32(b"HELLO CKBFS");
+    let metadata = [
+        ("author", "alice", 2),
+        ("description", "hello", 0),
+        ("license", "MIT", 1),
+    ];
+
+    let witness = build_head_witness(&[0u8; 32], 0, 0, 0, b"HELLO CKBFS");
+    let tx = env.publish_tx_with_args(
+        HelloData::new(checksum).metadata(&metadata).build(),
+        vec![witness.clone()],
+        vec![],
+        &[],
+    );
+    env.context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+
+    // keys must be sorted and unique
+    for metadata in [
+        [("license", "MIT", 1), ("author", "alice", 2)],
+        [("license", "MIT", 1), ("license", "MIT", 1)],
+    ] {
+        let data = HelloData::new(checksum).metadata(&metadata).build();
+        let tx = env.publish_tx_with_args(data, vec![witness.clone()], vec![], &[]);
+        let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+        assert_script_error(err, ERROR_ENCODING);
+    }
+
+    let output = env.ckbfs_output([7u8; 32].to_vec().into());
+    let input_out_point = env
+        .context
+        .create_cell(output, HelloData::new(checksum).metadata(&metadata).build());
+    let previous_tx_hash: [u8; 32] = input_out_point.tx_hash().unpack();
+    let witness = build_head_witness(&previous_tx_hash, 0, checksum, 0, &[]);
+
+    // mutable entries can be changed or removed, append-only entries extended, new entries added
+    let valid_updates: [&[(&str, &str, u8)]; 2] = [
+        &[("author", "alice, bob", 2), ("language", "en", 1), ("license", "MIT", 1)],
+        &[("author", "alice", 2), ("description", "hello world", 0), ("license", "MIT", 1)],
+    ];
+    for metadata in valid_updates {
+        let tx = env.update_tx_with_raw_data(
+            input_out_point.clone(),
+            HelloData::new(checksum).metadata(metadata).build(),
+            vec![witness.clone()],
+        );
+        env.context
+            .verify_tx(&tx, MAX_CYCLES)
+            .expect("pass verification");
+    }
+
+    let invalid_updates: [&[(&str, &str, u8)]; 5] = [
+        &[("author", "alice", 2), ("license", "GPL", 1)],
+        &[("author", "alice", 2), ("license", "MIT", 0)],
+        &[("author", "alice", 2)],
+        &[("author", "bob", 2), ("license", "MIT", 1)],
+        &[("author", "alice", 1), ("license", "MIT", 1)],
+    ];
+    for metadata in invalid_updates {
+        let tx = env.update_tx_with_raw_data(
+            input_out_point.clone(),
+            HelloData::new(checksum).metadata(metadata).build(),
+            vec![witness.clone()],
+        );
+        let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+        assert_script_error(err, ERROR_INVALID_METADATA_UPDATE);
+    }
+}
+
+#[test]
+fn test_ephemeral_file() {
+    let mut env = CKBFSEnv::new();
+    let witness = build_head_witness(&[0u8; 32], 0, 0, 0, b"HELLO CKBFS");
+    let data: CKBFSData = hello_data(adler32(b"HELLO CKBFS")).into();
+    let data = data.as_bytes();
+    let data_hash = CellOutput::calc_data_hash(&Loader::default().load_binary("ckb-adler32"));
+
+    // default hasher named explicitly, then the file flags
+    let mut args = data_hash.as_slice().to_vec();
+    args.extend_from_slice(&[2, 1]);
+    let tx = env.publish_tx_with_args(data.clone(), vec![witness.clone()], vec![], &args);
+    env.context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+
+    let mut invalid_args = args.clone();
+    invalid_args[33] = 0b10;
+    let tx = env.publish_tx_with_args(data.clone(), vec![witness.clone()], vec![], &invalid_args);
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_INVALID_TYPE_ARGS);
+
+    let mut type_id_args = [7u8; 32].to_vec();
+    type_id_args.extend_from_slice(&args);
+    let out_point = env.create_ckbfs_cell(type_id_args.clone().into(), hello_data(0));
+    let tx = env.destroy_tx(out_point, 0);
+    env.context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+
+    // permanent files keep being undestroyable
+    let out_point = env.create_ckbfs_cell([7u8; 32].to_vec().into(), hello_data(0));
+    let tx = env.destroy_tx(out_point, 0);
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_DELETION_FORBIDDEN);
+
+    // relative lock-up of 100 blocks
+    let lock_up: u64 = 0x8000_0000_0000_0000 | 100;
+    type_id_args.extend_from_slice(&lock_up.to_le_bytes());
+    let out_point = env.create_ckbfs_cell(type_id_args.into(), hello_data(0));
+    for since in [0, 0x8000_0000_0000_0000 | 99, 100] {
+        let tx = env.destroy_tx(out_point.clone(), since);
+        let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+        assert_script_error(err, ERROR_DELETION_LOCKED);
+    }
+    let tx = env.destroy_tx(out_point, lock_up);
+    env.context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+}
+
+fn manifest_content(entries: &[(&str, [u8; 32], u32)]) -> Vec<u8> {
+    entries
+        .iter()
+        .flat_map(|(path, type_id, checksum)| {
+            let target = Target {
+                type_id: *type_id,
+                checksum: *checksum,
+            };
+            ManifestEntry { path, target }.encode()
+        })
+        .collect()
+}
+
+fn manifest_data(content: &[u8]) -> CKBFSDataNative {
+    CKBFSDataNative {
+        index: 0,
+        checksum: adler32(content),
+        content_type: String::from_utf8(MANIFEST_CONTENT_TYPE.to_vec()).unwrap(),
+        filename: "site".to_string(),
+    }
+}
+
+#[test]
+fn test_manifest() {
+    let mut env = CKBFSEnv::new();
+    let content = manifest_content(&[("index.html", [1u8; 32], 1), ("assets/", [2u8; 32], 2)]);
+
+    // entries can span witnesses
+    let (head, tail) = content.split_at(10);
+    let witnesses = vec![build_head_witness(&[0u8; 32], 0, 0, 1, head), build_chain_witness(0, tail)];
+    let tx = env.publish_tx(manifest_data(&content), witnesses);
+    env.context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+
+    for content in [
+        manifest_content(&[("../index.html", [1u8; 32], 1)]),
+        manifest_content(&[("assets//logo.png", [1u8; 32], 1)]),
+        manifest_content(&[("index.html", [0u8; 32], 1)]),
+        content[..content.len() - 1].to_vec(),
+    ] {
+        let witness = build_head_witness(&[0u8; 32], 0, 0, 0, &content);
+        let tx = env.publish_tx(manifest_data(&content), vec![witness]);
+        let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+        assert_script_error(err, ERROR_INVALID_MANIFEST);
+    }
+
+    // appends add whole entries
+    let out_point = env.create_ckbfs_cell([7u8; 32].to_vec().into(), manifest_data(&content));
+    let previous_tx_hash: [u8; 32] = out_point.tx_hash().unpack();
+    let checksum = adler32(&content);
+    let appended = manifest_content(&[("index.html", [0u8; 32], 0), ("about.html", [3u8; 32], 3)]);
+    let mut data = manifest_data(&appended);
+    data.checksum = adler32_append(checksum, &appended);
+    let witness = build_head_witness(&previous_tx_hash, 0, checksum, 0, &appended);
+    let tx = env.update_tx(out_point.clone(), data.clone(), vec![witness]);
+    env.context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+
+    let witness = build_head_witness(&previous_tx_hash, 0, checksum, 0, &appended[1..]);
+    data.checksum = adler32_append(checksum, &appended[1..]);
+    let tx = env.update_tx(out_point, data, vec![witness]);
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_INVALID_MANIFEST);
+}
+
+#[test]
+fn test_manifest_resolution() {
+    let mut root = Manifest::from_content(&manifest_content(&[
+        ("index.html", [1u8; 32], 1),
+        ("assets/", [2u8; 32], 2),
+    ]))
+    .unwrap();
+    root.apply(&manifest_content(&[("index.html", [3u8; 32], 3), ("about.html", [4u8; 32], 4)]))
+        .unwrap();
+    let assets = manifest_content(&[("logo.png", [5u8; 32], 5), ("fonts/", [6u8; 32], 6)]);
+    let fonts = manifest_content(&[("mono.woff", [7u8; 32], 7)]);
+
+    let load = |target: &Target| match target.type_id {
+        [2, ..] => Ok(assets.clone()),
+        [6, ..] => Ok(fonts.clone()),
+        _ => Err(ckbfs_manifest::Error::NotFound),
+    };
+    let resolve = |path| ckbfs_manifest::resolve(&root, path, load).map(|target| target.checksum);
+    assert_eq!(resolve("index.html"), Ok(3));
+    assert_eq!(resolve("about.html"), Ok(4));
+    assert_eq!(resolve("assets/"), Ok(2));
+    assert_eq!(resolve("assets/logo.png"), Ok(5));
+    assert_eq!(resolve("assets/fonts/mono.woff"), Ok(7));
+    assert_eq!(resolve("assets/missing.png"), Err(ckbfs_manifest::Error::NotFound));
+    assert_eq!(resolve("index.html/x"), Err(ckbfs_manifest::Error::NotFound));
+    assert_eq!(resolve("assets/../index.html"), Err(ckbfs_manifest::Error::InvalidPath));
+
+    // removing a directory removes everything under it
+    root.apply(&manifest_content(&[("assets/", [0u8; 32], 0)])).unwrap();
+    assert_eq!(
+        ckbfs_manifest::resolve(&root, "assets/logo.png", load),
+        Err(ckbfs_manifest::Error::NotFound)
+    );
+}
+
+#[test]
+fn test_filename_and_content_type() {
+    let mut env = CKBFSEnv::new();
+    let witness = build_head_witness(&[0u8; 32], 0, 0, 0, b"HELLO CKBFS");
+    let publish = |env: &mut CKBFSEnv, filename: &[u8], content_type: &[u8]| {
+        // built by hand, the native data only takes valid strings
+        let bytes = |value: &[u8]| [&(value.len() as u32).to_le_bytes()[..], value].concat();
+        let data = encode_dynamic(&[
+            &0u32.to_le_bytes(),
+            &adler32(b"HELLO CKBFS").to_le_bytes(),
+            &bytes(content_type),
+            &bytes(filename),
+        ]);
+        let tx = env.publish_tx_with_args(data.into(), vec![witness.clone()], vec![], &[]);
+        env.context.verify_tx(&tx, MAX_CYCLES)
+    };
+
+    for (filename, content_type) in [
+        ("héllo 世界.txt", "text/plain"),
+        (".hidden", "text/html; charset=utf-8"),
+        ("data.json", "application/ld+json;profile=\"https://www.w3.org/ns/json-ld#expanded\""),
+    ] {
+        publish(&mut env, filename.as_bytes(), content_type.as_bytes()).expect("pass verification");
+    }
+
+    let long_name = vec![b'a'; 256];
+    let long_type = [b"text/".as_slice(), &[b'a'; 251]].concat();
+    for (filename, content_type, code) in [
+        (b"".as_slice(), b"text/plain".as_slice(), ERROR_EMPTY_FILENAME),
+        (b"hello\xff.txt", b"text/plain", ERROR_INVALID_FILENAME_ENCODING),
+        (b"dir/hello.txt", b"text/plain", ERROR_INVALID_FILENAME_CHARACTER),
+        (b"dir\\hello.txt", b"text/plain", ERROR_INVALID_FILENAME_CHARACTER),
+        (b"hello\0.txt", b"text/plain", ERROR_INVALID_FILENAME_CHARACTER),
+        (b"hello\n.txt", b"text/plain", ERROR_INVALID_FILENAME_CHARACTER),
+        (b"..", b"text/plain", ERROR_INVALID_FILENAME_CHARACTER),
+        (&long_name, b"text/plain", ERROR_FILENAME_TOO_LONG),
+        (b"hello.txt", b"", ERROR_INVALID_CONTENT_TYPE),
+        (b"hello.txt", b"text", ERROR_INVALID_CONTENT_TYPE),
+        (b"hello.txt", b"text/", ERROR_INVALID_CONTENT_TYPE),
+        (b"hello.txt", b"text/plain\r\n", ERROR_INVALID_CONTENT_TYPE),
+        (b"hello.txt", b"text/plain; charset", ERROR_INVALID_CONTENT_TYPE),
+        (b"hello.txt", b"text/plain; charset=\"utf-8", ERROR_INVALID_CONTENT_TYPE),
+        (&long_name, &long_type, ERROR_FILENAME_TOO_LONG),
+        (b"hello.txt", &long_type, ERROR_CONTENT_TYPE_TOO_LONG),
+    ] {
+        let err = publish(&mut env, filename, content_type).unwrap_err();
+        assert_script_error(err, code);
+    }
+}
+
+#[test]
+fn test_batch_publish_with_shared_witnesses() {
+    let mut env = CKBFSEnv::new();
+    let checksum = adler32(b"HELLO CKBFS");
+
+    let witnesses = vec![
+        build_head_witness(&[0u8; 32], 0, 0, 1, b"HELLO"),
+        build_chain_witness(0, b" CKBFS"),
+        build_head_witness(&[0u8; 32], 0, 0, 0, b"HELLO CKBFS"),
+    ];
+    let tx = env.batch_publish_tx(checksum, &[0, 2], witnesses.clone());
+    env.context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+
+    // same head witness
+    let tx = env.batch_publish_tx(checksum, &[2, 2], witnesses);
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_SHARED_WITNESS);
+
+    // different heads, same tail
+    let witnesses = vec![
+        build_head_witness(&[0u8; 32], 0, 0, 2, b"HELLO"),
+        build_head_witness(&[0u8; 32], 0, 0, 2, b"HELLO"),
+        build_chain_witness(0, b" CKBFS"),
+    ];
+    let tx = env.batch_publish_tx(checksum, &[0, 1], witnesses);
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_SHARED_WITNESS);
+}
+
+// drops the ckb-adler32 cell dep, which the inline Adler32 build does not need
+fn without_hasher_dep(env: &CKBFSEnv, tx: TransactionView) -> TransactionView {
+    let cell_deps: Vec<CellDep> = tx
+        .cell_deps()
+        .into_iter()
+        .filter(|cell_dep| cell_dep != &env.adler32_dep)
+        .collect();
+    tx.as_advanced_builder().set_cell_deps(cell_deps).build()
+}
+
+#[test]
+fn test_inline_adler32() {
+    let mut env = CKBFSEnv::new();
+    let mut inline_env = CKBFSEnv::with_contract("ckbfs-inline-adler32");
+    let witnesses = vec![
+        build_head_witness(&[0u8; 32], 0, 0, 1, b"HELLO"),
+        build_chain_witness(0, b" CKBFS"),
+    ];
+    let checksum = adler32(b"HELLO CKBFS");
+
+    let tx = env.publish_tx(hello_data(checksum), witnesses.clone());
+    let spawn_cycles = env
+        .context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    let tx = inline_env.publish_tx(hello_data(checksum), witnesses.clone());
+    let tx = without_hasher_dep(&inline_env, tx);
+    let inline_cycles = inline_env
+        .context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    assert!(inline_cycles < spawn_cycles, "{inline_cycles} >= {spawn_cycles}");
+
+    // same errors as the spawned hasher
+    let tx = inline_env.publish_tx(hello_data(checksum + 1), witnesses);
+    let tx = without_hasher_dep(&inline_env, tx);
+    let err = inline_env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_CHECKSUM_MISMATCH);
+    let witnesses = vec![
+        build_head_witness(&[0u8; 32], 0, 0, 1, b"HELLO"),
+        build_chain_witness(1, b" CKBFS"),
+    ];
+    let tx = inline_env.publish_tx(hello_data(checksum), witnesses);
+    let tx = without_hasher_dep(&inline_env, tx);
+    let err = inline_env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_WITNESS_INDEX_REPEATED);
+    let witnesses = vec![
+        wrap_witness(&[], None, Some(build_head_witness(&[0u8; 32], 0, 0, 1, b"HELLO"))),
+        build_chain_witness(0, b" CKBFS"),
+    ];
+    let tx = env.publish_tx(hello_data(checksum), witnesses.clone());
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_INVALID_WITNESS);
+    let tx = inline_env.publish_tx(hello_data(checksum), witnesses);
+    let tx = without_hasher_dep(&inline_env, tx);
+    let err = inline_env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_INVALID_WITNESS);
+
+    // v2 files use the default hasher as well
+    let input = inline_env.create_input();
+    let output = inline_env.ckbfs_output(build_type_id(&input, 0).to_vec().into());
+    let tx = TransactionBuilder::default()
+        .input(input)
+        .output(output)
+        .output_data(hello_data_v2(vec![1, 2], checksum, vec![]).pack())
+        .witness(Bytes::new().pack())
+        .witness(build_v2_witness(b"HELLO").pack())
+        .witness(build_v2_witness(b" CKBFS").pack())
+        .build();
+    let tx = inline_env.context.complete_tx(tx);
+    inline_env
+        .context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+
+    // custom hashers are still spawned
+    let witness = build_head_witness(&[0u8; 32], 0, 0, 0, b"HELLO CKBFS");
+    let data: CKBFSData = hello_data(checksum).into();
+    let tx = inline_env.publish_tx_with_args(data.as_bytes(), vec![witness], vec![], &[3u8; 32]);
+    let tx = without_hasher_dep(&inline_env, tx);
+    let err = inline_env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_NO_CHECKSUM_HASHER_FOUND);
+}
+
+// Runs a hasher through hasher-runner, a lock which spawns the hasher of `hasher_bin` with `args`
+// from witness 0 and exits with its exit code, `witnesses` follow the args
+fn run_hasher(hasher_bin: &str, args: &[&str], witnesses: Vec<Bytes>) -> Result<u64, Error> {
+    let mut context = Context::default();
+    let hasher_bin = Loader::default().load_binary(hasher_bin);
+    let hasher_hash = CellOutput::calc_data_hash(&hasher_bin);
+    let hasher_out_point = context.deploy_cell(hasher_bin);
+    let runner_out_point = context.deploy_cell(Loader::test_contracts().load_binary("hasher-runner"));
+    let always_success_out_point = context.deploy_cell(ALWAYS_SUCCESS.clone());
+    let runner_lock = context
+        .build_script(&runner_out_point, hasher_hash.as_bytes())
+        .expect("script");
+    let always_success_lock = context
+        .build_script(&always_success_out_point, Bytes::new())
+        .expect("script");
+
+    let input = context.create_cell(
+        CellOutput::new_builder().capacity(1000u64.pack()).lock(runner_lock).build(),
+        Bytes::new(),
+    );
+    let args: Vec<u8> = args.iter().flat_map(|arg| arg.bytes().chain([0])).collect();
+    let tx = TransactionBuilder::default()
+        .input(CellInput::new_builder().previous_output(input).build())
+        .output(CellOutput::new_builder().capacity(1000u64.pack()).lock(always_success_lock).build())
+        .output_data(Bytes::new().pack())
+        .cell_dep(code_dep(hasher_out_point))
+        .witness(Bytes::from(args).pack())
+        .witnesses(witnesses.into_iter().map(|witness| witness.pack()).collect::<Vec<_>>())
+        .build();
+    let tx = context.complete_tx(tx);
+    context.verify_tx(&tx, MAX_CYCLES)
+}
+
+#[test]
+fn test_hasher_exit_codes() {
+    let checksum = format!("0x{}", hex_le(adler32(b"HELLO")));
+    run_hasher("ckb-adler32", &["0", "HELLO", &checksum], vec![]).expect("pass verification");
+
+    // every failure of ckb-adler32 exits with its own code, CKBFS fails with the same code
+    let head_witness = build_head_witness(&[0u8; 32], 0, 0, 0, b"HELLO");
+    let mut v2_head_witness = head_witness.to_vec();
+    v2_head_witness[5] = 0;
+    let cases: Vec<(Vec<&str>, Vec<Bytes>, i8)> = vec![
+        (vec!["0", "HELLO", "0x00000000"], vec![], ERROR_VALIDATE_FAILURE),
+        (vec!["0", "HELLO", "0xzzzzzzzz"], vec![], ERROR_INVALID_ARG),
+        (vec!["0", "HELLO", "HELLO"], vec![], ERROR_INVALID_ARG),
+        (vec!["0", "HELLO", "0x0000"], vec![], ERROR_INVALID_ARG_LENGTH),
+        (vec!["0", "HELLO"], vec![], ERROR_MISSING_ARGS),
+        (vec!["9", "HELLO", &checksum], vec![], ERROR_UNKNOWN_MODE),
+        (vec!["2", "5", "0", "0", &checksum], vec![], ERROR_INVALID_SOURCE),
+        (vec!["2", "2", "0", "1", &checksum], vec![], ERROR_INVALID_OFFSET),
+        (vec!["3", "5", &checksum], vec![], ERROR_WITNESS_MISSING),
+        (vec!["1", "0x0800000001000000", &checksum], vec![], ERROR_INVALID_WITNESS_INDEXES),
+        (vec!["1", "0x01", &checksum], vec![], ERROR_INVALID_WITNESS_INDEXES),
+        (vec!["1", "0x0100000001000000", &checksum], vec![Bytes::from_static(b"CK")], ERROR_INVALID_WITNESS),
+        (vec!["3", "1", &checksum], vec![v2_head_witness.into()], ERROR_INVALID_WITNESS_VERSION),
+        (vec!["3", "1", &checksum], vec![head_witness], 0),
+    ];
+    for (args, witnesses, code) in cases {
+        match run_hasher("ckb-adler32", &args, witnesses) {
+            Ok(_) => assert_eq!(code, 0, "{args:?} passed"),
+            Err(err) => assert_script_error(err, code),
+        }
+    }
+
+    // hashers which can not recover from a checksum
+    let checksum = format!("0x{}", hex_le(blake2b_hasher(b"HELLO").checksum()));
+    let err = run_hasher("ckb-blake2b", &["0", "HELLO", &checksum, "0x01000000"], vec![]).unwrap_err();
+    assert_script_error(err, ERROR_UNRECOVERABLE);
+}
+
+fn hex_le(value: u32) -> String {
+    value.to_le_bytes().iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[test]
+fn test_crc32c_hasher() {
+    let mut env = CKBFSEnv::new();
+    let crc32c_bin = Loader::default().load_binary("ckb-crc32c");
+    let hasher_args = CellOutput::calc_data_hash(&crc32c_bin).as_slice().to_vec();
+    let crc32c_dep = code_dep(env.context.deploy_cell(crc32c_bin));
+    let witnesses = vec![
+        build_head_witness(&[0u8; 32], 0, 0, 1, b"HELLO"),
+        build_chain_witness(0, b" CKBFS"),
+    ];
+    let checksum = crc32c(b"HELLO CKBFS");
+    let data: CKBFSData = hello_data(checksum).into();
+    let tx = env.publish_tx_with_args(
+        data.as_bytes(),
+        witnesses.clone(),
+        vec![crc32c_dep.clone()],
+        &hasher_args,
+    );
+    env.context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+
+    // an Adler32 checksum does not pass the CRC-32C hasher
+    let data: CKBFSData = hello_data(adler32(b"HELLO CKBFS")).into();
+    let tx = env.publish_tx_with_args(data.as_bytes(), witnesses, vec![crc32c_dep.clone()], &hasher_args);
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_CHECKSUM_MISMATCH);
+
+    // append recovers from the previous checksum
+    let mut args = [7u8; 32].to_vec();
+    args.extend_from_slice(&hasher_args);
+    let input_out_point = env.create_ckbfs_cell(args.into(), hello_data(checksum));
+    let previous_tx_hash: [u8; 32] = input_out_point.tx_hash().unpack();
+    let appended_checksum = crc32c_append(checksum, b" AGAIN");
+    assert_eq!(appended_checksum, crc32c(b"HELLO CKBFS AGAIN"));
+    let witness = build_head_witness(&previous_tx_hash, 0, checksum, 0, b" AGAIN");
+    let tx = env.update_tx(input_out_point.clone(), hello_data(appended_checksum), vec![witness]);
+    let tx = tx.as_advanced_builder().cell_dep(crc32c_dep.clone()).build();
+    env.context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    let witness = build_head_witness(&previous_tx_hash, 0, checksum, 0, b" AGAIN");
+    let tx = env.update_tx(input_out_point, hello_data(crc32c(b" AGAIN")), vec![witness]);
+    let tx = tx.as_advanced_builder().cell_dep(crc32c_dep.clone()).build();
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_CHECKSUM_MISMATCH);
+
+    // the CRC-32C of empty content is 0, an append still recovers from it
+    assert_eq!(crc32c(b""), 0);
+    let witness = build_head_witness(&[0u8; 32], 0, 0, 0, b"");
+    let data: CKBFSData = hello_data(0).into();
+    let tx = env.publish_tx_with_args(data.as_bytes(), vec![witness], vec![crc32c_dep.clone()], &hasher_args);
+    env.context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    let mut args = [8u8; 32].to_vec();
+    args.extend_from_slice(&hasher_args);
+    let input_out_point = env.create_ckbfs_cell(args.into(), hello_data(0));
+    let previous_tx_hash: [u8; 32] = input_out_point.tx_hash().unpack();
+    let witness = build_head_witness(&previous_tx_hash, 0, 0, 0, b"HELLO");
+    let tx = env.update_tx(input_out_point, hello_data(crc32c(b"HELLO")), vec![witness]);
+    let tx = tx.as_advanced_builder().cell_dep(crc32c_dep.clone()).build();
+    env.context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+
+    // v2 witnesses are hashed the same way
+    let input = env.create_input();
+    let mut args = build_type_id(&input, 0).to_vec();
+    args.extend_from_slice(&hasher_args);
+    let output = env.ckbfs_output(args.into());
+    let tx = TransactionBuilder::default()
+        .input(input)
+        .output(output)
+        .output_data(hello_data_v2(vec![1, 2], checksum, vec![]).pack())
+        .cell_dep(crc32c_dep)
+        .witness(Bytes::new().pack())
+        .witness(build_v2_witness(b"HELLO").pack())
+        .witness(build_v2_witness(b" CKBFS").pack())
+        .build();
+    let tx = env.context.complete_tx(tx);
+    env.context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+}
+
+#[test]
+fn test_adler32_combine() {
+    let content: Vec<u8> = (0..200_000u32).map(|i| (i * 31 + 7) as u8).collect();
+    // a length of B past the Adler32 modulus, or a multiple of it, wraps the sums
+    for split in [0, 1, 1000, 65521, 131042, 150_000, content.len()] {
+        let (a, b) = content.split_at(split);
+        assert_eq!(
+            ckbfs_adler32::combine(adler32(a), adler32(b), b.len() as u64),
+            adler32(&content),
+            "split at {split}"
+        );
+    }
+}
+
+#[test]
+fn test_resumable_blake2b() {
+    let content: Vec<u8> = (0..1000u32).map(|i| (i * 7) as u8).collect();
+    for split in [0, 1, 127, 128, 129, 256, 999, 1000] {
+        let mut hasher = ckbfs_blake2b::Blake2b::new();
+        hasher.update(&content[..split]);
+        let mut hasher = ckbfs_blake2b::Blake2b::deserialize(&hasher.serialize()).expect("state");
+        hasher.update(&content[split..]);
+        assert_eq!(hasher.finalize(), ckb_testtool::ckb_hash::blake2b_256(&content), "split at {split}");
+    }
+    assert_eq!(
+        ckbfs_blake2b::Blake2b::new().finalize(),
+        ckb_testtool::ckb_hash::blake2b_256([])
+    );
+
+    // BLAKE2b-512("abc"), appendix A of RFC 7693
+    let expected = "ba80a53f981c4d0d6a2797b69f12f6e94c212f14685ac4b74b12bb6fdbffa2d1\
+                    7d87c5392aab792dc252d5de4533cc9518d38aa8dbf1925ab92386edd4009923";
+    let expected: Vec<u8> = (0..expected.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&expected[i..i + 2], 16).unwrap())
+        .collect();
+    let mut hasher = ckbfs_blake2b::Blake2b::with_params(64, &[0u8; 16]);
+    hasher.update(b"abc");
+    assert_eq!(hasher.finalize_full().to_vec(), expected);
+
+    // whole blocks are compressed only when more content follows
+    let mut state = ckbfs_blake2b::Blake2b::new().serialize();
+    state[64] = 128;
+    assert!(ckbfs_blake2b::Blake2b::deserialize(&state).is_err());
+    state.push(0);
+    state[64] = 1;
+    assert!(ckbfs_blake2b::Blake2b::deserialize(&state).is_err());
+}
+
+fn blake2b_hasher(content: &[u8]) -> ckbfs_blake2b::Blake2b {
+    let mut hasher = ckbfs_blake2b::Blake2b::new();
+    hasher.update(content);
+    hasher
+}
+
+#[test]
+fn test_blake2b_hasher() {
+    let mut env = CKBFSEnv::new();
+    let blake2b_bin = Loader::default().load_binary("ckb-blake2b");
+    let hasher_args = CellOutput::calc_data_hash(&blake2b_bin).as_slice().to_vec();
+    let blake2b_dep = code_dep(env.context.deploy_cell(blake2b_bin));
+    let witnesses = vec![
+        build_head_witness(&[0u8; 32], 0, 0, 1, b"HELLO"),
+        build_chain_witness(0, b" CKBFS"),
+    ];
+    let hasher = blake2b_hasher(b"HELLO CKBFS");
+    let checksum = hasher.checksum();
+    let data = HelloData::new(checksum).hasher_digest(Some(hasher.finalize())).build();
+    let tx = env.publish_tx_with_args(data, witnesses.clone(), vec![blake2b_dep.clone()], &hasher_args);
+    env.context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+
+    let data = HelloData::new(adler32(b"HELLO CKBFS")).hasher_digest(Some(hasher.finalize())).build();
+    let tx = env.publish_tx_with_args(data, witnesses.clone(), vec![blake2b_dep.clone()], &hasher_args);
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_CHECKSUM_MISMATCH);
+
+    // Rule 39: the full digest is kept in cell data
+    for hasher_digest in [None, Some(blake2b_hasher(b"HELLO CKBFZ").finalize())] {
+        let data = HelloData::new(checksum).hasher_digest(hasher_digest).build();
+        let tx = env.publish_tx_with_args(data, witnesses.clone(), vec![blake2b_dep.clone()], &hasher_args);
+        let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+        assert_script_error(err, ERROR_HASHER_DIGEST_MISMATCH);
+    }
+
+    // append resumes from the state carried in input_type of the head witness
+    let mut args = [7u8; 32].to_vec();
+    args.extend_from_slice(&hasher_args);
+    let args: Bytes = args.into();
+    let input_data = HelloData::new(checksum).hasher_digest(Some(hasher.finalize())).build();
+    let output = env.ckbfs_output(args.clone());
+    let input_out_point = env.context.create_cell(output, input_data);
+    let previous_tx_hash: [u8; 32] = input_out_point.tx_hash().unpack();
+    let appended = blake2b_hasher(b"HELLO CKBFS AGAIN");
+    let appended_data = HelloData::new(appended.checksum()).hasher_digest(Some(appended.finalize())).build();
+    let head_witness = build_head_witness(&previous_tx_hash, 0, checksum, 0, b" AGAIN");
+    let append_tx = |env: &mut CKBFSEnv, witness: Bytes| {
+        let tx = env.update_tx_with_raw_data(input_out_point.clone(), appended_data.clone(), vec![witness]);
+        tx.as_advanced_builder().cell_dep(blake2b_dep.clone()).build()
+    };
+    let witness = wrap_witness(&[], Some(hasher.serialize().into()), Some(head_witness.clone()));
+    let tx = append_tx(&mut env, witness.clone());
+    env.context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+
+    // the appended digest is the one of the whole content
+    let tx = env.update_tx_with_raw_data(
+        input_out_point.clone(),
+        HelloData::new(appended.checksum())
+            .hasher_digest(Some(blake2b_hasher(b" AGAIN").finalize()))
+            .build(),
+        vec![witness],
+    );
+    let tx = tx.as_advanced_builder().cell_dep(blake2b_dep.clone()).build();
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_HASHER_DIGEST_MISMATCH);
+
+    // no state to resume from
+    let tx = append_tx(&mut env, head_witness.clone());
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_INVALID_STATE);
+    let witness = wrap_witness(&[], None, Some(head_witness.clone()));
+    let tx = append_tx(&mut env, witness);
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_INVALID_STATE);
+
+    // the state of other content does not finalize to the previous checksum
+    let forged = blake2b_hasher(b"HELLO CKBFZ");
+    let witness = wrap_witness(&[], Some(forged.serialize().into()), Some(head_witness));
+    let tx = append_tx(&mut env, witness);
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_INVALID_STATE);
+
+    // a state found to finalize to the previous checksum still does not finalize to the previous digest,
+    // the input checksum is set to the one of the forged state in place of a brute forced collision
+    let input_data = HelloData::new(forged.checksum()).hasher_digest(Some(hasher.finalize())).build();
+    let output = env.ckbfs_output(args);
+    let input_out_point = env.context.create_cell(output, input_data);
+    let previous_tx_hash: [u8; 32] = input_out_point.tx_hash().unpack();
+    let head_witness = build_head_witness(&previous_tx_hash, 0, forged.checksum(), 0, b" AGAIN");
+    let mut forged_appended = forged.clone();
+    forged_appended.update(b" AGAIN");
+    let witness = wrap_witness(&[], Some(forged.serialize().into()), Some(head_witness));
+    let output_data = HelloData::new(forged_appended.checksum())
+        .hasher_digest(Some(forged_appended.finalize()))
+        .build();
+    let tx = env.update_tx_with_raw_data(input_out_point, output_data, vec![witness]);
+    let tx = tx.as_advanced_builder().cell_dep(blake2b_dep.clone()).build();
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_HASHER_DIGEST_MISMATCH);
+}
+
+#[test]
+fn test_merkle_range_proof() {
+    use ckbfs_merkle::{prove_range, root, ChunkTree, Error, RangeProof, CHUNK_SIZE};
+
+    let content: Vec<u8> = (0..3 * CHUNK_SIZE as u32 + 1000).map(|i| (i * 13) as u8).collect();
+    let length = content.len() as u64;
+    let file_root = root(&content);
+
+    // pushing content in pieces, and resuming from a serialized state, leads to the same root
+    for split in [0, 1, CHUNK_SIZE, CHUNK_SIZE + 1, 2 * CHUNK_SIZE, content.len()] {
+        let mut tree = ChunkTree::new();
+        tree.update(&content[..split]);
+        let mut tree = ChunkTree::deserialize(&tree.serialize()).expect("state");
+        tree.update(&content[split..]);
+        assert_eq!(tree.root(), file_root, "split at {split}");
+    }
+    let mut state = ChunkTree::new().serialize();
+    state.push(0);
+    assert_eq!(ChunkTree::deserialize(&state), Err(Error::InvalidState));
+    // a state missing a peak for its full chunk count is rejected before content is pushed
+    let mut tree = ChunkTree::new();
+    tree.update(&content[..3 * CHUNK_SIZE]);
+    let state = tree.serialize();
+    assert_eq!(ChunkTree::deserialize(&state[..state.len() - 32]), Err(Error::InvalidState));
+
+    let chunk = CHUNK_SIZE as u64;
+    for (start, end) in [(0, 1), (chunk - 1, chunk + 1), (0, length), (5000, 9000), (length - 1, length)] {
+        let proof = prove_range(&content, start, end).expect("proof");
+        let proof = RangeProof::decode(&proof.encode()).expect("decode");
+        let data = &content[start as usize..end as usize];
+        proof.verify(&file_root, start, data).expect("verify");
+
+        // the bytes are pinned to their position
+        let mut tampered = data.to_vec();
+        tampered[0] ^= 1;
+        assert_eq!(proof.verify(&file_root, start, &tampered), Err(Error::InvalidProof));
+        if start > 0 {
+            let shifted = &content[start as usize - 1..end as usize - 1];
+            assert!(proof.verify(&file_root, start - 1, shifted).is_err());
+        }
+    }
+
+    // only whole chunks of the range are sent along
+    let proof = prove_range(&content, 5000, 5001).expect("proof");
+    assert_eq!(proof.prefix.len() + proof.suffix.len() + 1, CHUNK_SIZE);
+    assert_eq!(prove_range(&content, 10, 10), Err(Error::InvalidRange));
+    assert_eq!(prove_range(&content, 0, length + 1), Err(Error::InvalidRange));
+
+    // a range wrapping around the end of u64 is rejected instead of overflowing
+    let proof = RangeProof { length: u64::MAX, ..proof };
+    assert_eq!(proof.compute_root(u64::MAX, &content[..2]), Err(Error::InvalidProof));
+    assert_eq!(proof.compute_root(u64::MAX - 1, &content[..1]), Err(Error::InvalidProof));
+}
+
+#[test]
+fn test_merkle_hasher() {
+    let mut env = CKBFSEnv::new();
+    let merkle_bin = Loader::default().load_binary("ckb-merkle");
+    let hasher_args = CellOutput::calc_data_hash(&merkle_bin).as_slice().to_vec();
+    let merkle_dep = code_dep(env.context.deploy_cell(merkle_bin));
+    let content: Vec<u8> = (0..10_000u32).map(|i| (i * 13) as u8).collect();
+    let witnesses = vec![
+        build_head_witness(&[0u8; 32], 0, 0, 1, &content[..5000]),
+        build_chain_witness(0, &content[5000..]),
+    ];
+    let mut tree = ckbfs_merkle::ChunkTree::new();
+    tree.update(&content);
+    let checksum = tree.checksum();
+    let data = HelloData::new(checksum).hasher_digest(Some(tree.root())).build();
+    let tx = env.publish_tx_with_args(data.clone(), witnesses.clone(), vec![merkle_dep.clone()], &hasher_args);
+    env.context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+
+    // Rule 39: the full root is kept on-chain in the last field, and proofs are checked against it
+    let root: [u8; 32] = data[data.len() - 32..].try_into().unwrap();
+    let proof = ckbfs_merkle::prove_range(&content, 4000, 4200).expect("proof");
+    proof.verify(&root, 4000, &content[4000..4200]).expect("verify");
+    let data = HelloData::new(checksum).hasher_digest(None).build();
+    let tx = env.publish_tx_with_args(data, witnesses.clone(), vec![merkle_dep.clone()], &hasher_args);
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_HASHER_DIGEST_MISMATCH);
+
+    let data = HelloData::new(adler32(&content)).hasher_digest(Some(tree.root())).build();
+    let tx = env.publish_tx_with_args(data, witnesses, vec![merkle_dep.clone()], &hasher_args);
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_CHECKSUM_MISMATCH);
+
+    // append pushes onto the chunk tree state carried in input_type of the head witness
+    let mut args = [7u8; 32].to_vec();
+    args.extend_from_slice(&hasher_args);
+    let args: Bytes = args.into();
+    let output = env.ckbfs_output(args.clone());
+    let input_data = HelloData::new(checksum).hasher_digest(Some(tree.root())).build();
+    let input_out_point = env.context.create_cell(output, input_data);
+    let previous_tx_hash: [u8; 32] = input_out_point.tx_hash().unpack();
+    let mut appended = content.clone();
+    appended.extend_from_slice(b" AGAIN");
+    let appended_root = ckbfs_merkle::root(&appended);
+    let appended_data = HelloData::new(ckbfs_merkle::checksum(&appended_root))
+        .hasher_digest(Some(appended_root))
+        .build();
+    let head_witness = build_head_witness(&previous_tx_hash, 0, checksum, 0, b" AGAIN");
+    let append_tx = |env: &mut CKBFSEnv, witness: Bytes| {
+        let tx = env.update_tx_with_raw_data(input_out_point.clone(), appended_data.clone(), vec![witness]);
+        tx.as_advanced_builder().cell_dep(merkle_dep.clone()).build()
+    };
+    let witness = wrap_witness(&[], Some(tree.serialize().into()), Some(head_witness.clone()));
+    let tx = append_tx(&mut env, witness);
+    env.context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+
+    let tx = append_tx(&mut env, head_witness.clone());
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_INVALID_STATE);
+    let mut forged = ckbfs_merkle::ChunkTree::new();
+    forged.update(&content[1..]);
+    let witness = wrap_witness(&[], Some(forged.serialize().into()), Some(head_witness));
+    let tx = append_tx(&mut env, witness);
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_INVALID_STATE);
+
+    // a state leading to the previous checksum, but not to the previous root, as a brute forced one would
+    let output = env.ckbfs_output(args);
+    let input_data = HelloData::new(forged.checksum()).hasher_digest(Some(tree.root())).build();
+    let input_out_point = env.context.create_cell(output, input_data);
+    let previous_tx_hash: [u8; 32] = input_out_point.tx_hash().unpack();
+    let head_witness = build_head_witness(&previous_tx_hash, 0, forged.checksum(), 0, b" AGAIN");
+    let witness = wrap_witness(&[], Some(forged.serialize().into()), Some(head_witness));
+    forged.update(b" AGAIN");
+    let output_data = HelloData::new(forged.checksum()).hasher_digest(Some(forged.root())).build();
+    let tx = env.update_tx_with_raw_data(input_out_point, output_data, vec![witness]);
+    let tx = tx.as_advanced_builder().cell_dep(merkle_dep.clone()).build();
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_HASHER_DIGEST_MISMATCH);
+}
+
+// concatenation head witness: "CKBFS" + 0x83 + position and checksum of A + position and checksum of B
+pub fn build_concat_witness(a: (&[u8; 32], u32, u32), b: (&[u8; 32], u32, u32)) -> Bytes {
+    let mut witness = b"CKBFS\x83".to_vec();
+    for (tx_hash, index, checksum) in [a, b] {
+        witness.extend_from_slice(tx_hash);
+        witness.extend_from_slice(&index.to_le_bytes());
+        witness.extend_from_slice(&checksum.to_le_bytes());
+    }
+    Bytes::from(witness)
+}
+
+#[test]
+fn test_concatenate() {
+    for contract in ["ckbfs", "ckbfs-inline-adler32"] {
+        let mut env = CKBFSEnv::with_contract(contract);
+        let checksum_a = adler32(b"HELLO CKBFS");
+        let checksum_b = adler32(b" AND GOODBYE");
+        let output = env.ckbfs_output([7u8; 32].to_vec().into());
+        let a = env.context.create_cell(output, HelloData::new(checksum_a).length(11).build());
+        let output = env.ckbfs_output([8u8; 32].to_vec().into());
+        let b = env.context.create_cell(output, HelloData::new(checksum_b).length(12).build());
+        let a_tx_hash: [u8; 32] = a.tx_hash().unpack();
+        let b_tx_hash: [u8; 32] = b.tx_hash().unpack();
+        let deps = vec![code_dep(a.clone()), code_dep(b.clone())];
+        let without_hasher = |env: &CKBFSEnv, tx| match contract {
+            "ckbfs" => tx,
+            _ => without_hasher_dep(env, tx),
+        };
+
+        // checksum of A || B is combined from the sources, no content is hashed
+        let checksum = adler32(b"HELLO CKBFS AND GOODBYE");
+        let witness = build_concat_witness((&a_tx_hash, 0, checksum_a), (&b_tx_hash, 0, checksum_b));
+        let data = HelloData::new(checksum).length(23).build();
+        let tx = env.publish_tx_with_args(data, vec![witness.clone()], deps.clone(), &[]);
+        let tx = without_hasher(&env, tx);
+        env.context
+            .verify_tx(&tx, MAX_CYCLES)
+            .expect("pass verification");
+
+        // the concatenation can be wrapped in WitnessArgs as well
+        let wrapped = wrap_witness(&[], None, Some(witness.clone()));
+        let data = HelloData::new(checksum).length(23).build();
+        let tx = env.publish_tx_with_args(data, vec![wrapped], deps.clone(), &[]);
+        let tx = without_hasher(&env, tx);
+        env.context
+            .verify_tx(&tx, MAX_CYCLES)
+            .expect("pass verification");
+
+        let data = HelloData::new(checksum + 1).length(23).build();
+        let tx = env.publish_tx_with_args(data, vec![witness.clone()], deps.clone(), &[]);
+        let tx = without_hasher(&env, tx);
+        let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+        assert_script_error(err, ERROR_CHECKSUM_MISMATCH);
+
+        // order matters
+        let reversed = build_concat_witness((&b_tx_hash, 0, checksum_b), (&a_tx_hash, 0, checksum_a));
+        let data = HelloData::new(checksum).length(23).build();
+        let tx = env.publish_tx_with_args(data, vec![reversed], deps.clone(), &[]);
+        let tx = without_hasher(&env, tx);
+        let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+        assert_script_error(err, ERROR_CHECKSUM_MISMATCH);
+
+        let data = HelloData::new(checksum).length(22).build();
+        let tx = env.publish_tx_with_args(data, vec![witness.clone()], deps.clone(), &[]);
+        let tx = without_hasher(&env, tx);
+        let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+        assert_script_error(err, ERROR_LENGTH_MISMATCH);
+
+        let data = HelloData::new(checksum).content_hash(Some([0u8; 32])).build();
+        let tx = env.publish_tx_with_args(data, vec![witness.clone()], deps.clone(), &[]);
+        let tx = without_hasher(&env, tx);
+        let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+        assert_script_error(err, ERROR_CONTENT_HASH_MISMATCH);
+
+        // sources must be referenced in CellDeps
+        let data = HelloData::new(checksum).length(23).build();
+        let tx = env.publish_tx_with_args(data, vec![witness], vec![code_dep(a.clone())], &[]);
+        let tx = without_hasher(&env, tx);
+        let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+        assert_script_error(err, ERROR_INVALID_CONCAT_SOURCE);
+
+        // combining needs the length of B
+        let b = env.create_ckbfs_cell([9u8; 32].to_vec().into(), hello_data(checksum_b));
+        let b_tx_hash: [u8; 32] = b.tx_hash().unpack();
+        let witness = build_concat_witness((&a_tx_hash, 0, checksum_a), (&b_tx_hash, 0, checksum_b));
+        let deps = vec![code_dep(a), code_dep(b)];
+        let tx = env.publish_tx(hello_data(checksum), vec![witness]);
+        let tx = tx.as_advanced_builder().cell_deps(deps).build();
+        let tx = without_hasher(&env, tx);
+        let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+        assert_script_error(err, ERROR_INVALID_CONCAT_SOURCE);
+    }
+}
+
+#[test]
+fn test_concatenate_with_other_hasher() {
+    let mut env = CKBFSEnv::new();
+    let checksum_a = adler32(b"HELLO CKBFS");
+    let checksum_b = adler32(b" AND GOODBYE");
+    let output = env.ckbfs_output([7u8; 32].to_vec().into());
+    let a = env.context.create_cell(output, HelloData::new(checksum_a).length(11).build());
+    let output = env.ckbfs_output([8u8; 32].to_vec().into());
+    let b = env.context.create_cell(output, HelloData::new(checksum_b).length(12).build());
+    let a_tx_hash: [u8; 32] = a.tx_hash().unpack();
+    let b_tx_hash: [u8; 32] = b.tx_hash().unpack();
+    let witness = build_concat_witness((&a_tx_hash, 0, checksum_a), (&b_tx_hash, 0, checksum_b));
+
+    // the sources use the default hasher, the new file names ckb-crc32c
+    let hasher_args = CellOutput::calc_data_hash(&Loader::default().load_binary("ckb-crc32c"));
+    let checksum = adler32(b"HELLO CKBFS AND GOODBYE");
+    let tx = env.publish_tx_with_args(
+        HelloData::new(checksum).length(23).build(),
+        vec![witness],
+        vec![code_dep(a), code_dep(b)],
+        hasher_args.as_slice(),
+    );
+    let err = env.context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_INVALID_CONCAT_SOURCE);
+}